@@ -1,39 +1,127 @@
-use crate::{expressions::Literal, interpreter::Interpreter};
-use std::rc::Rc;
+use crate::{expressions::Literal, interpreter::Interpreter, tokens::Token};
+use std::{fmt, rc::Rc};
 
-// pub trait DebugFn: Fn(&mut Interpreter, &Vec<String>, Vec<Literal>)
+pub type Function =
+    Rc<dyn Fn(&mut Interpreter, &Token, &[String], Vec<Literal>) -> Result<Literal, ()>>;
 
-// impl<F> DebugFn for F where F: Fn(&mut Interpreter, &Vec<String>, Vec<Literal>) {}
+#[derive(Clone, Copy, Debug)]
+pub enum Arity {
+    Exact(usize),
+    Between(usize, usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exact(n) => count == n,
+            Arity::Between(min, max) => (min..=max).contains(&count),
+            Arity::AtLeast(min) => count >= min,
+        }
+    }
+}
 
-// impl std::fmt::Debug for DisplayFnT {
-//     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-//         write!(f, "DisplayFunction")
-//     }
-// }
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arity::Exact(n) => write!(f, "{n}"),
+            Arity::Between(min, max) => write!(f, "{min} to {max}"),
+            Arity::AtLeast(min) => write!(f, "at least {min}"),
+        }
+    }
+}
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Callable {
+    name: Option<String>,
     parameters: Vec<String>,
-    func: Rc<dyn Fn(&mut Interpreter, &Vec<String>, Vec<Literal>) -> Result<Literal, ()>>,
+    arity: Arity,
+    source: Option<String>,
+    func: Function,
 }
 
 impl Callable {
     pub fn new(
+        name: Option<String>,
         parameters: Vec<String>,
-        func: Rc<dyn Fn(&mut Interpreter, &Vec<String>, Vec<Literal>) -> Result<Literal, ()>>,
+        source: String,
+        func: Function,
     ) -> Callable {
-        Callable { parameters, func }
+        Callable {
+            name,
+            arity: Arity::Exact(parameters.len()),
+            parameters,
+            source: Some(source),
+            func,
+        }
+    }
+
+    pub fn native(name: &str, arity: Arity, func: Function) -> Callable {
+        Callable {
+            name: Some(name.to_owned()),
+            parameters: Vec::new(),
+            arity,
+            source: None,
+            func,
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn arity(&self) -> Arity {
+        self.arity
     }
 
-    pub fn arity(&self) -> usize {
-        self.parameters.len()
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
     }
 
     pub fn call(
         &self,
         interpreter: &mut Interpreter,
+        paren: &Token,
         arguments: Vec<Literal>,
     ) -> Result<Literal, ()> {
-        (self.func)(interpreter, &self.parameters, arguments)
+        (self.func)(interpreter, paren, &self.parameters, arguments)
+    }
+}
+
+impl fmt::Display for Callable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "<fn {name}>"),
+            None => write!(f, "<fn>"),
+        }
+    }
+}
+
+impl fmt::Debug for Callable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Arity;
+
+    #[test]
+    fn arities_accept_their_argument_counts() {
+        assert!(Arity::Exact(2).accepts(2));
+        assert!(!Arity::Exact(2).accepts(3));
+        assert!(Arity::Between(2, 3).accepts(3));
+        assert!(!Arity::Between(2, 3).accepts(1));
+        assert!(Arity::AtLeast(2).accepts(2));
+        assert!(Arity::AtLeast(2).accepts(40));
+        assert!(!Arity::AtLeast(2).accepts(1));
+    }
+
+    #[test]
+    fn arities_display_as_they_read_in_errors() {
+        assert_eq!(Arity::Exact(1).to_string(), "1");
+        assert_eq!(Arity::Between(2, 3).to_string(), "2 to 3");
+        assert_eq!(Arity::AtLeast(2).to_string(), "at least 2");
     }
 }
@@ -1,16 +1,24 @@
 use crate::expressions::Literal;
-use std::collections::HashMap;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct Environment {
     pub values: HashMap<String, Literal>,
-    pub parent: Option<Box<Environment>>,
+    pub builtins: HashSet<String>,
+    constants: HashSet<String>,
+    pub parent: Option<Rc<RefCell<Environment>>>,
 }
 
 impl Environment {
-    pub fn new(parent: Option<Box<Environment>>) -> Environment {
+    pub fn new(parent: Option<Rc<RefCell<Environment>>>) -> Environment {
         Environment {
             values: HashMap::new(),
+            builtins: HashSet::new(),
+            constants: HashSet::new(),
             parent,
         }
     }
@@ -19,23 +27,167 @@ impl Environment {
         self.values.contains_key(name)
     }
 
-    pub fn bind(&mut self, name: &str, value: Literal) {
+    pub fn is_builtin(&self, name: &str) -> bool {
         if self.contains(name) {
-            self.values.insert(name.to_owned(), value);
-        } else if let Some(ref mut parent) = self.parent {
-            parent.bind(name, value);
+            self.builtins.contains(name)
+        } else if let Some(ref parent) = self.parent {
+            parent.borrow().is_builtin(name)
+        } else {
+            false
+        }
+    }
+
+    pub fn define(&mut self, name: &str, value: Literal) {
+        self.constants.remove(name);
+        self.builtins.remove(name);
+        self.values.insert(name.to_owned(), value);
+    }
+
+    pub fn define_constant(&mut self, name: &str, value: Literal) {
+        self.constants.insert(name.to_owned());
+        self.builtins.remove(name);
+        self.values.insert(name.to_owned(), value);
+    }
+
+    pub fn is_constant(&self, name: &str) -> bool {
+        if self.contains(name) {
+            self.constants.contains(name)
+        } else if let Some(ref parent) = self.parent {
+            parent.borrow().is_constant(name)
+        } else {
+            false
+        }
+    }
+
+    pub fn is_constant_at(&self, distance: usize, name: &str) -> bool {
+        if distance == 0 {
+            self.constants.contains(name)
+        } else if let Some(ref parent) = self.parent {
+            parent.borrow().is_constant_at(distance - 1, name)
+        } else {
+            false
+        }
+    }
+
+    pub fn assign(&mut self, name: &str, value: Literal) -> Result<(), ()> {
+        if let Some(slot) = self.values.get_mut(name) {
+            *slot = value;
+            Ok(())
+        } else if let Some(ref parent) = self.parent {
+            parent.borrow_mut().assign(name, value)
         } else {
-            self.values.insert(name.to_owned(), value);
+            Err(())
         }
     }
 
-    pub fn get(&self, name: &str) -> Option<&Literal> {
+    pub fn get(&self, name: &str) -> Option<Literal> {
         if let Some(value) = self.values.get(name) {
-            Some(value)
+            Some(value.clone())
         } else if let Some(ref parent) = self.parent {
-            parent.get(name)
+            parent.borrow().get(name)
         } else {
             None
         }
     }
+
+    pub fn get_at(&self, distance: usize, name: &str) -> Option<Literal> {
+        if distance == 0 {
+            self.values.get(name).cloned()
+        } else {
+            self.parent.as_ref()?.borrow().get_at(distance - 1, name)
+        }
+    }
+
+    pub fn assign_at(&mut self, distance: usize, name: &str, value: Literal) -> Result<(), ()> {
+        if distance == 0 {
+            let slot = self.values.get_mut(name).ok_or(())?;
+            *slot = value;
+            Ok(())
+        } else if let Some(ref parent) = self.parent {
+            parent.borrow_mut().assign_at(distance - 1, name, value)
+        } else {
+            Err(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Environment;
+    use crate::{expressions::Literal, testing};
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn a_shadowed_builtin_can_be_reassigned() {
+        assert_eq!(
+            testing::run("var clock = 5; clock = 6; print clock;"),
+            "6\n"
+        );
+    }
+
+    #[test]
+    fn a_builtin_shadowed_by_a_constant_is_reported_as_a_constant() {
+        let output = testing::run("const clock = 5; clock = 6;");
+
+        assert!(!output.contains("builtin"), "{output}");
+        assert!(output.contains("constant"), "{output}");
+    }
+
+    #[test]
+    fn builtins_still_cannot_be_reassigned() {
+        assert!(testing::run("clock = 6;").contains("Cannot reassign builtin"));
+    }
+
+    fn chain() -> Environment {
+        let mut globals = Environment::new(None);
+        globals.define("a", Literal::Number(1.0));
+        let globals = Rc::new(RefCell::new(globals));
+
+        let mut local = Environment::new(Some(globals));
+        local.define("a", Literal::Number(2.0));
+        local
+    }
+
+    fn at(environment: &Environment, distance: usize, name: &str) -> Option<String> {
+        environment
+            .get_at(distance, name)
+            .map(|value| value.to_string())
+    }
+
+    #[test]
+    fn get_at_reads_exactly_the_given_depth() {
+        let local = chain();
+
+        assert_eq!(at(&local, 0, "a").as_deref(), Some("2"));
+        assert_eq!(at(&local, 1, "a").as_deref(), Some("1"));
+        assert_eq!(at(&local, 2, "a"), None);
+        assert_eq!(at(&local, 0, "b"), None);
+    }
+
+    #[test]
+    fn assign_at_writes_exactly_the_given_depth() {
+        let mut local = chain();
+
+        assert!(local.assign_at(1, "a", Literal::Number(3.0)).is_ok());
+        assert_eq!(at(&local, 0, "a").as_deref(), Some("2"));
+        assert_eq!(at(&local, 1, "a").as_deref(), Some("3"));
+        assert!(local.assign_at(0, "b", Literal::Nil).is_err());
+    }
+
+    #[test]
+    fn assign_updates_the_nearest_existing_binding() {
+        let mut local = chain();
+
+        assert!(local.assign("a", Literal::Number(3.0)).is_ok());
+        assert_eq!(at(&local, 0, "a").as_deref(), Some("3"));
+        assert_eq!(at(&local, 1, "a").as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn assign_never_creates_a_binding() {
+        let mut local = chain();
+
+        assert!(local.assign("b", Literal::Nil).is_err());
+        assert!(!local.contains("b"));
+    }
 }
@@ -1,4 +1,11 @@
+use std::{
+    cell::{Cell, RefCell},
+    env, fmt,
+    io::{IsTerminal, Write, stdout},
+};
+
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ErrorType {
     TokenError,
     ParserError,
@@ -6,30 +13,208 @@ pub enum ErrorType {
     RuntimeError,
 }
 
+struct Output(Box<dyn Write>);
+
+impl fmt::Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<output>")
+    }
+}
+
 #[derive(Debug)]
 pub struct Error {
     file: String,
-    lines: Option<Vec<String>>,
+    lines: RefCell<Vec<String>>,
+    captured: RefCell<Option<Vec<String>>>,
+    had_errors: Cell<bool>,
+    color: Cell<bool>,
+    output: RefCell<Output>,
 }
 
 impl Error {
     pub fn new(file: &str, source: Option<String>) -> Error {
         Error {
             file: file.to_owned(),
-            lines: source.map(|s| s.lines().map(|l| l.to_owned()).collect()),
+            lines: RefCell::new(
+                source.map_or_else(Vec::new, |s| s.lines().map(|l| l.to_owned()).collect()),
+            ),
+            captured: RefCell::new(None),
+            had_errors: Cell::new(false),
+            color: Cell::new(
+                stdout().is_terminal()
+                    && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+            ),
+            output: RefCell::new(Output(Box::new(stdout()))),
         }
     }
 
-    pub fn report(&self, (line, column): (&usize, &usize), typ: ErrorType, message: &str) {
-        if let Some(lines) = &self.lines {
-            println!("{}", lines[*line - 1].trim());
+    // Diagnostics go to stdout unless the host sends them elsewhere, and are
+    // only colored when they go to a terminal.
+    pub fn set_output(&self, output: impl Write + 'static) {
+        self.color.set(false);
+        self.output.replace(Output(Box::new(output)));
+    }
+
+    pub fn had_errors(&self) -> bool {
+        self.had_errors.get()
+    }
+
+    pub fn reset(&self) {
+        self.had_errors.set(false);
+    }
+
+    // The REPL adds each input as it arrives, so lines keep counting up across
+    // inputs and errors can still echo the line they point at.
+    pub fn add_source(&self, source: &str) -> usize {
+        let mut lines = self.lines.borrow_mut();
+        let first = lines.len() + 1;
+
+        lines.extend(source.lines().map(|l| l.to_owned()));
+        first
+    }
+
+    pub fn capture<T>(&self, f: impl FnOnce() -> T) -> (T, Vec<String>) {
+        let outer = self.captured.replace(Some(Vec::new()));
+        let result = f();
+        let captured = self.captured.replace(outer).unwrap_or_default();
+
+        (result, captured)
+    }
+
+    pub fn report(&self, location: (&usize, &usize), typ: ErrorType, message: &str) {
+        self.report_span(location, 1, typ, message);
+    }
+
+    pub fn report_span(
+        &self,
+        (line, column): (&usize, &usize),
+        width: usize,
+        typ: ErrorType,
+        message: &str,
+    ) {
+        if let Some(captured) = self.captured.borrow_mut().as_mut() {
+            captured.push(message.to_owned());
+            return;
         }
 
-        println!(
-            "{}^ -- Here",
-            " ".repeat(column + self.lines.is_none() as usize)
+        self.had_errors.set(true);
+
+        let rendered = self.render((*line, *column), width, typ, message);
+        _ = writeln!(self.output.borrow_mut().0, "{rendered}");
+    }
+
+    fn render(
+        &self,
+        (line, column): (usize, usize),
+        width: usize,
+        typ: ErrorType,
+        message: &str,
+    ) -> String {
+        let (red, reset) = if self.color.get() {
+            ("\x1b[1;31m", "\x1b[0m")
+        } else {
+            ("", "")
+        };
+
+        let lines = self.lines.borrow();
+
+        let (source, padding) = match lines.get(line - 1) {
+            Some(source) => {
+                let indent = source.chars().take_while(|c| c.is_whitespace()).count();
+                let source = source.trim();
+
+                // Keep tabs before the caret so it lines up with the echoed
+                // line however wide the terminal renders them.
+                let padding: String = source
+                    .chars()
+                    .take(column.saturating_sub(1 + indent))
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+
+                (format!("{source}\n"), padding)
+            }
+            None => (String::new(), " ".repeat(column + 1)),
+        };
+
+        let carets = "^".repeat(width.max(1));
+
+        format!(
+            "{source}{padding}{red}{carets} -- Here{reset}\n{} @ Line {line} - {red}{typ:?}{reset}: {message}",
+            &self.file
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ErrorType};
+
+    fn render(source: &str, location: (usize, usize), width: usize) -> String {
+        Error::new("<test>", Some(source.to_owned())).render(
+            location,
+            width,
+            ErrorType::RuntimeError,
+            "Oops.",
+        )
+    }
+
+    #[test]
+    fn caret_lines_up_on_a_tab_indented_line() {
+        assert_eq!(
+            render("\t\tprint x;", (1, 9), 1),
+            "print x;\n      ^ -- Here\n<test> @ Line 1 - RuntimeError: Oops."
+        );
+    }
+
+    #[test]
+    fn caret_keeps_tabs_inside_the_line() {
+        assert_eq!(
+            render("a\tb;", (1, 3), 1),
+            "a\tb;\n \t^ -- Here\n<test> @ Line 1 - RuntimeError: Oops."
         );
+    }
 
-        println!("{} @ Line {line} - {typ:?}: {message}", &self.file);
+    #[test]
+    fn spans_are_underlined_with_one_caret_per_column() {
+        assert_eq!(
+            render("print abc;", (1, 7), 3),
+            "print abc;\n      ^^^ -- Here\n<test> @ Line 1 - RuntimeError: Oops."
+        );
+    }
+
+    #[test]
+    fn color_wraps_the_carets_and_the_error_type() {
+        let error = Error::new("<test>", Some("x;".to_owned()));
+        error.color.set(true);
+
+        assert_eq!(
+            error.render((1, 1), 1, ErrorType::ParserError, "Oops."),
+            "x;\n\x1b[1;31m^ -- Here\x1b[0m\n<test> @ Line 1 - \x1b[1;31mParserError\x1b[0m: Oops."
+        );
+    }
+
+    #[test]
+    fn redirected_output_is_never_colored() {
+        let error = Error::new("<test>", Some("x;".to_owned()));
+        error.color.set(true);
+        error.set_output(std::io::sink());
+
+        assert!(
+            !error
+                .render((1, 1), 1, ErrorType::ParserError, "Oops.")
+                .contains('\x1b')
+        );
+    }
+
+    #[test]
+    fn added_sources_keep_counting_lines() {
+        let error = Error::new("<repl>", None);
+
+        assert_eq!(error.add_source("a\nb\n"), 1);
+        assert_eq!(error.add_source("c\n"), 3);
+        assert_eq!(
+            error.render((3, 1), 1, ErrorType::RuntimeError, "Oops."),
+            "c\n^ -- Here\n<repl> @ Line 3 - RuntimeError: Oops."
+        );
     }
 }
@@ -1,5 +1,11 @@
-use crate::{callable::Callable, statements::Stmt, tokens::Token};
-use std::fmt;
+use crate::{callable::Callable, environment::Environment, statements::Stmt, tokens::Token};
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt,
+    rc::Rc,
+};
 
 #[derive(Clone, Debug)]
 pub enum Literal {
@@ -7,9 +13,88 @@ pub enum Literal {
     String(String),
     Boolean(bool),
     Callable(Callable),
+    List(Rc<RefCell<Vec<Literal>>>),
+    Set(Rc<RefCell<HashSet<Key>>>),
+    Map(Rc<RefCell<HashMap<Key, Literal>>>),
+    Thunk(Rc<RefCell<Thunk>>),
     Nil,
 }
 
+// Numbers are stored by bit pattern with NaN and -0 canonicalized, so equal
+// values always hash the same.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    Nil,
+    Boolean(bool),
+    Number(u64),
+    String(String),
+}
+
+impl Key {
+    pub fn from_literal(literal: &Literal) -> Result<Key, String> {
+        match literal {
+            Literal::Nil => Ok(Key::Nil),
+            Literal::Boolean(b) => Ok(Key::Boolean(*b)),
+            Literal::Number(n) if n.is_nan() => Ok(Key::Number(f64::NAN.to_bits())),
+            Literal::Number(n) => Ok(Key::Number((n + 0.0).to_bits())),
+            Literal::String(s) => Ok(Key::String(s.to_owned())),
+            value => Err(format!(
+                "Can not use '{}' as a key, {} values are not hashable.",
+                value,
+                value.type_name()
+            )),
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Key::Nil => 0,
+            Key::Boolean(..) => 1,
+            Key::Number(..) => 2,
+            Key::String(..) => 3,
+        }
+    }
+
+    pub fn to_literal(&self) -> Literal {
+        match self {
+            Key::Nil => Literal::Nil,
+            Key::Boolean(b) => Literal::Boolean(*b),
+            Key::Number(bits) => Literal::Number(f64::from_bits(*bits)),
+            Key::String(s) => Literal::String(s.to_owned()),
+        }
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Key) -> Ordering {
+        match (self, other) {
+            (Key::Boolean(a), Key::Boolean(b)) => a.cmp(b),
+            (Key::Number(a), Key::Number(b)) => f64::from_bits(*a).total_cmp(&f64::from_bits(*b)),
+            (Key::String(a), Key::String(b)) => a.cmp(b),
+            (a, b) => a.rank().cmp(&b.rank()),
+        }
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Key) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug)]
+pub enum Thunk {
+    Pending(Expr, Rc<RefCell<Environment>>),
+    Forcing,
+    Forced(Literal),
+}
+
+impl Thunk {
+    pub fn pending(expr: Expr, environment: Rc<RefCell<Environment>>) -> Literal {
+        Literal::Thunk(Rc::new(RefCell::new(Thunk::Pending(expr, environment))))
+    }
+}
+
 impl Literal {
     pub fn is_truthy(&self) -> bool {
         match self {
@@ -19,6 +104,153 @@ impl Literal {
             Literal::Number(..) => true,
             Literal::String(..) => true,
             Literal::Callable(..) => true,
+            Literal::List(..) => true,
+            Literal::Set(..) => true,
+            Literal::Map(..) => true,
+            Literal::Thunk(..) => true,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Literal::Number(..) => "number",
+            Literal::String(..) => "string",
+            Literal::Boolean(..) => "boolean",
+            Literal::Callable(..) => "function",
+            Literal::List(..) => "list",
+            Literal::Set(..) => "set",
+            Literal::Map(..) => "map",
+            Literal::Thunk(..) => "thunk",
+            Literal::Nil => "nil",
+        }
+    }
+
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0, &mut Vec::new());
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, depth: usize, seen: &mut Vec<*const ()>) {
+        let pointer = match self {
+            Literal::List(list) => Rc::as_ptr(list) as *const (),
+            Literal::Map(map) => Rc::as_ptr(map) as *const (),
+            value => {
+                out.push_str(&value.to_string());
+                return;
+            }
+        };
+
+        let (open, close) = match self {
+            Literal::List(..) => ('[', ']'),
+            _ => ('{', '}'),
+        };
+
+        if seen.contains(&pointer) {
+            out.push(open);
+            out.push_str("...");
+            out.push(close);
+            return;
+        }
+
+        // Map entries are written in key order, the same as compact Display.
+        let entries: Vec<(Option<Literal>, Literal)> = match self {
+            Literal::List(list) => list
+                .borrow()
+                .iter()
+                .map(|value| (None, value.clone()))
+                .collect(),
+            Literal::Map(map) => {
+                let map = map.borrow();
+                let mut keys: Vec<_> = map.keys().collect();
+                keys.sort();
+
+                keys.into_iter()
+                    .map(|key| (Some(key.to_literal()), map[key].clone()))
+                    .collect()
+            }
+            _ => unreachable!(),
+        };
+
+        if entries.is_empty() {
+            out.push(open);
+            out.push(close);
+            return;
+        }
+
+        seen.push(pointer);
+        out.push(open);
+        out.push('\n');
+
+        for (i, (key, value)) in entries.iter().enumerate() {
+            out.push_str(&"  ".repeat(depth + 1));
+
+            if let Some(key) = key {
+                out.push_str(&format!("{key}: "));
+            }
+
+            value.write_pretty(out, depth + 1, seen);
+
+            if i + 1 < entries.len() {
+                out.push(',');
+            }
+
+            out.push('\n');
+        }
+
+        out.push_str(&"  ".repeat(depth));
+        out.push(close);
+        seen.pop();
+    }
+}
+
+impl Literal {
+    // Lists and maps can hold themselves, so the ones already being written
+    // are tracked and print as `[...]` or `{...}` when they come up again.
+    fn write_compact(&self, f: &mut fmt::Formatter, seen: &mut Vec<*const ()>) -> fmt::Result {
+        match self {
+            Literal::List(list) => {
+                let pointer = Rc::as_ptr(list) as *const ();
+
+                if seen.contains(&pointer) {
+                    return write!(f, "[...]");
+                }
+
+                seen.push(pointer);
+                write!(f, "[")?;
+                for (i, value) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.write_compact(f, seen)?;
+                }
+                seen.pop();
+                write!(f, "]")
+            }
+            Literal::Map(map) => {
+                let pointer = Rc::as_ptr(map) as *const ();
+
+                if seen.contains(&pointer) {
+                    return write!(f, "{{...}}");
+                }
+
+                let map = map.borrow();
+                let mut keys: Vec<_> = map.keys().collect();
+                keys.sort();
+
+                seen.push(pointer);
+                write!(f, "{{")?;
+                for (i, key) in keys.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", key.to_literal())?;
+                    map[key].write_compact(f, seen)?;
+                }
+                seen.pop();
+                write!(f, "}}")
+            }
+            value => write!(f, "{value}"),
         }
     }
 }
@@ -26,10 +258,32 @@ impl Literal {
 impl fmt::Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            // Whole numbers print without a fraction, and only magnitudes
+            // outside 1e-6..1e21 switch to exponent notation.
+            Literal::Number(n)
+                if n.is_finite() && *n != 0.0 && !(1e-6..1e21).contains(&n.abs()) =>
+            {
+                write!(f, "{:e}", n)
+            }
             Literal::Number(n) => write!(f, "{}", n),
             Literal::String(s) => write!(f, "{}", s),
             Literal::Boolean(b) => write!(f, "{}", b),
-            Literal::Callable(..) => write!(f, "<fn>"),
+            Literal::Callable(callable) => write!(f, "{callable}"),
+            Literal::List(..) | Literal::Map(..) => self.write_compact(f, &mut Vec::new()),
+            Literal::Set(set) => {
+                let mut keys: Vec<_> = set.borrow().iter().cloned().collect();
+                keys.sort();
+
+                write!(f, "{{")?;
+                for (i, key) in keys.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", key.to_literal())?;
+                }
+                write!(f, "}}")
+            }
+            Literal::Thunk(..) => write!(f, "<thunk>"),
             Literal::Nil => write!(f, "nil"),
         }
     }
@@ -54,10 +308,12 @@ pub enum Expr {
     },
     Variable {
         name: Token,
+        id: usize,
     },
     Assign {
         name: Token,
         value: Box<Expr>,
+        id: usize,
     },
     Logical {
         left: Box<Expr>,
@@ -67,6 +323,193 @@ pub enum Expr {
     Call {
         callee: Box<Expr>,
         paren: Token,
-        arguments: Vec<Stmt>,
+        arguments: Vec<Expr>,
+    },
+    Lambda {
+        params: Vec<String>,
+        body: Vec<Stmt>,
+        source: String,
+        line: usize,
+        column: usize,
+    },
+    Map {
+        entries: Vec<(Expr, Expr)>,
+        brace: Token,
+    },
+    Block {
+        statements: Vec<Stmt>,
+        declares: bool,
+        brace: Token,
     },
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        bracket: Token,
+    },
+    Set {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        value: Box<Expr>,
+        bracket: Token,
+    },
+}
+
+impl Expr {
+    pub fn location(&self) -> Option<(usize, usize)> {
+        match self {
+            Expr::Binary { left, operator, .. } | Expr::Logical { left, operator, .. } => left
+                .location()
+                .or_else(|| Some((*operator.location().0, *operator.location().1))),
+            Expr::Grouping { expression } => expression.location(),
+            Expr::Literal { .. } => None,
+            Expr::Unary {
+                operator: token, ..
+            }
+            | Expr::Variable { name: token, .. }
+            | Expr::Assign { name: token, .. }
+            | Expr::Map { brace: token, .. }
+            | Expr::Block { brace: token, .. } => {
+                let (line, column) = token.location();
+                Some((*line, *column))
+            }
+            Expr::Call { callee, .. } => callee.location(),
+            Expr::Index { object, .. } | Expr::Set { object, .. } => object.location(),
+            Expr::Lambda { line, column, .. } => Some((*line, *column)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Key, Literal};
+    use crate::testing;
+    use std::collections::HashSet;
+
+    #[test]
+    fn a_list_holding_itself_prints_once() {
+        let output = testing::run("var l = split(\"a,b\", \",\"); l[0] = l; print l;");
+
+        assert_eq!(output, "[[...], b]\n");
+    }
+
+    #[test]
+    fn a_map_holding_itself_prints_once() {
+        let output = testing::run("var m = {\"a\": 1}; m[\"self\"] = m; print m;");
+
+        assert_eq!(output, "{a: 1, self: {...}}\n");
+    }
+
+    #[test]
+    fn a_cycle_through_a_list_and_a_map_prints_once() {
+        let output = testing::run(
+            "var l = split(\"a\", \",\"); var m = {\"l\": l}; l[0] = m; print l; print m;",
+        );
+
+        assert_eq!(output, "[{l: [...]}]\n{l: [{...}]}\n");
+    }
+
+    #[test]
+    fn pretty_prints_maps_inside_lists() {
+        let output = testing::run(
+            "var l = split(\"a\", \",\"); l[0] = {\"b\": 1, \"a\": {}}; print pretty(l);",
+        );
+
+        assert_eq!(output, "[\n  {\n    a: {},\n    b: 1\n  }\n]\n");
+    }
+
+    #[test]
+    fn pretty_prints_a_cycle_through_a_map_once() {
+        let output =
+            testing::run("var l = split(\"a\", \",\"); l[0] = {\"l\": l}; print pretty(l);");
+
+        assert_eq!(output, "[\n  {\n    l: [...]\n  }\n]\n");
+    }
+
+    #[test]
+    fn pretty_prints_a_map_holding_itself_once() {
+        let output = testing::run("var m = {\"a\": 1}; m[\"m\"] = m; print pretty(m);");
+
+        assert_eq!(output, "{\n  a: 1,\n  m: {...}\n}\n");
+    }
+
+    #[test]
+    fn a_value_shared_without_a_cycle_prints_in_full() {
+        let output = testing::run("var m = {\"a\": 1}; print {\"x\": m, \"y\": m};");
+
+        assert_eq!(output, "{x: {a: 1}, y: {a: 1}}\n");
+    }
+
+    #[test]
+    fn keys_fold_signed_zeros_and_nans() {
+        let key = |n: f64| Key::from_literal(&Literal::Number(n)).unwrap();
+
+        assert_eq!(key(-0.0), key(0.0));
+        assert_eq!(key(f64::NAN), key(-f64::NAN));
+        assert_ne!(key(1.0), key(0.0));
+    }
+
+    #[test]
+    fn keys_order_by_type_then_value() {
+        let mut keys = [
+            Key::String("a".to_owned()),
+            Key::Number(2.0_f64.to_bits()),
+            Key::Boolean(true),
+            Key::Nil,
+            Key::Number(1.0_f64.to_bits()),
+        ];
+        keys.sort();
+
+        assert_eq!(
+            keys.iter()
+                .map(Key::to_literal)
+                .map(|key| key.to_string())
+                .collect::<Vec<_>>(),
+            ["nil", "true", "1", "2", "a"]
+        );
+    }
+
+    fn key(literal: Literal) -> Key {
+        Key::from_literal(&literal).unwrap()
+    }
+
+    #[test]
+    fn equal_numbers_make_one_key() {
+        let keys: HashSet<Key> = [1.0, 1.0_f64.floor(), 0.5 + 0.5, f64::NAN, f64::NAN / 2.0]
+            .into_iter()
+            .map(|n| key(Literal::Number(n)))
+            .collect();
+
+        assert_eq!(keys.len(), 2);
+        assert!(keys.contains(&key(Literal::Number(f64::NAN))));
+    }
+
+    #[test]
+    fn string_keys_compare_by_value() {
+        assert_eq!(
+            key(Literal::String("a".to_owned())),
+            key(Literal::String(String::from("a")))
+        );
+        assert_ne!(
+            key(Literal::String("1".to_owned())),
+            key(Literal::Number(1.0))
+        );
+    }
+
+    #[test]
+    fn number_keys_sort_numerically() {
+        let mut keys = [10.0, -1.0, 2.0, -20.0].map(|n| key(Literal::Number(n)));
+        keys.sort();
+
+        assert_eq!(
+            keys.map(|key| key.to_literal().to_string()),
+            ["-20", "-1", "2", "10"]
+        );
+    }
+
+    #[test]
+    fn unhashable_values_explain_themselves() {
+        let list = testing::run("print set(enumerate(range(0, 1)));");
+
+        assert!(list.contains("Can not use '[0, 0]' as a key, list values are not hashable."));
+    }
 }
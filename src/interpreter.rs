@@ -2,23 +2,112 @@ use crate::{
     callable::Callable,
     environment::Environment,
     error::{Error, ErrorType},
-    expressions::{Expr, Literal},
+    expressions::{Expr, Key, Literal, Thunk},
+    natives,
     statements::Stmt,
     tokens::Token,
 };
 use std::{
-    collections::HashMap,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{self, BufRead, BufReader, Write, stdin, stdout},
     rc::Rc,
-    time::{SystemTime, UNIX_EPOCH},
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
+#[derive(Debug)]
+pub enum Flow {
+    Normal(Literal),
+    Break,
+    Continue,
+    Return(Literal),
+}
+
+pub enum Control {
+    Continue,
+    Step,
+    Abort,
+}
+
+type HookFn = dyn FnMut(&Stmt, Option<(usize, usize)>) -> Control;
+
+pub struct Hook(Box<HookFn>);
+
+impl fmt::Debug for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<hook>")
+    }
+}
+
+// Time as seen by the profiling modes. Hosts and tests can swap in their own
+// source to get repeatable timings.
+#[derive(Clone)]
+pub struct Clock(Arc<dyn Fn() -> Duration + Send + Sync>);
+
+impl Clock {
+    pub fn new(now: impl Fn() -> Duration + Send + Sync + 'static) -> Clock {
+        Clock(Arc::new(now))
+    }
+
+    pub fn system() -> Clock {
+        let start = Instant::now();
+        Clock::new(move || start.elapsed())
+    }
+
+    pub fn now(&self) -> Duration {
+        (self.0)()
+    }
+}
+
+impl fmt::Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<clock>")
+    }
+}
+
+pub struct Input(Box<dyn BufRead>);
+
+impl fmt::Debug for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<input>")
+    }
+}
+
+pub struct Output(Box<dyn Write>);
+
+impl fmt::Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<output>")
+    }
+}
+
+#[derive(Debug, Default)]
+struct Timings {
+    lines: HashMap<usize, Duration>,
+    nested: Vec<Duration>,
+}
+
 #[derive(Debug)]
 pub struct Interpreter<'src> {
-    error: &'src Error,
-    environment: Environment,
-    pub locals: HashMap<Expr, usize>,
+    pub error: &'src Error,
+    environment: Rc<RefCell<Environment>>,
+    globals: Rc<RefCell<Environment>>,
+    pub locals: HashMap<usize, usize>,
     repl: bool,
     is_loop: bool,
+    trace: Option<HashMap<String, usize>>,
+    timings: Option<Timings>,
+    clock: Clock,
+    lazy: bool,
+    hook: Option<Hook>,
+    input: Input,
+    output: Output,
+    features: HashSet<String>,
+    depth: usize,
+    max_depth: usize,
+    defers: Vec<Vec<Stmt>>,
 }
 
 impl Interpreter<'_> {
@@ -26,146 +115,680 @@ impl Interpreter<'_> {
         error: &'src Error,
         environment: Environment,
         repl: bool,
+        max_depth: usize,
     ) -> Interpreter<'src> {
-        let mut environment = Environment::new(Some(Box::new(environment)));
-
-        environment.values.insert(
-            String::from("clock"),
-            Literal::Callable(Callable::new(
-                vec![],
-                Rc::new(|_, _, _| {
-                    Ok(Literal::Number(
-                        SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .unwrap()
-                            .as_secs_f64(),
-                    ))
-                }),
-            )),
-        );
+        let mut environment = Environment::new(Some(Rc::new(RefCell::new(environment))));
+
+        natives::define(&mut environment);
+
+        let environment = Rc::new(RefCell::new(environment));
 
         Interpreter {
             error,
+            globals: environment.clone(),
             environment,
             locals: HashMap::new(),
             repl,
             is_loop: false,
+            trace: None,
+            timings: None,
+            clock: Clock::system(),
+            lazy: false,
+            hook: None,
+            input: Input(Box::new(BufReader::new(stdin()))),
+            output: Output(Box::new(stdout())),
+            features: HashSet::new(),
+            depth: 0,
+            max_depth,
+            defers: Vec::new(),
+        }
+    }
+
+    pub fn set_hook(
+        &mut self,
+        hook: impl FnMut(&Stmt, Option<(usize, usize)>) -> Control + 'static,
+    ) {
+        self.hook = Some(Hook(Box::new(hook)));
+    }
+
+    // input() reads lines from here, which is stdin unless the host replaces
+    // it.
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.input = Input(Box::new(input));
+    }
+
+    pub fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        self.input.0.read_line(line)
+    }
+
+    // Everything the script prints goes here, which is stdout unless the host
+    // replaces it.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Output(Box::new(output));
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Literal) {
+        self.globals.borrow_mut().define(name, value);
+    }
+
+    pub fn hoist_functions(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            if let Stmt::Function {
+                name,
+                params,
+                body,
+                source,
+                ..
+            } = stmt
+            {
+                let func = self.function(
+                    Some(name.clone()),
+                    params.clone(),
+                    body.clone(),
+                    source.clone(),
+                );
+                self.set_global(name, func);
+            }
+        }
+    }
+
+    pub fn enable_lazy(&mut self) {
+        self.lazy = true;
+    }
+
+    pub fn set_feature(&mut self, name: &str, enabled: bool) {
+        if enabled {
+            self.features.insert(name.to_owned());
+        } else {
+            self.features.remove(name);
+        }
+    }
+
+    pub fn has_feature(&self, name: &str) -> bool {
+        self.features.contains(name)
+    }
+
+    pub fn force(&mut self, value: Literal, location: (&usize, &usize)) -> Result<Literal, ()> {
+        let Literal::Thunk(thunk) = value else {
+            return Ok(value);
+        };
+
+        let (expr, environment) = match thunk.replace(Thunk::Forcing) {
+            Thunk::Pending(expr, environment) => (expr, environment),
+            Thunk::Forcing => {
+                self.error.report(
+                    location,
+                    ErrorType::RuntimeError,
+                    "Lazy value depends on itself.",
+                );
+                return Err(());
+            }
+            Thunk::Forced(value) => {
+                thunk.replace(Thunk::Forced(value.clone()));
+                return Ok(value);
+            }
+        };
+
+        let previous = std::mem::replace(&mut self.environment, environment.clone());
+        let res = self.evaluate(&expr);
+        self.environment = previous;
+
+        match res {
+            Ok(value) => {
+                thunk.replace(Thunk::Forced(value.clone()));
+                Ok(value)
+            }
+            Err(()) => {
+                thunk.replace(Thunk::Pending(expr, environment));
+                Err(())
+            }
+        }
+    }
+
+    pub fn enable_trace(&mut self) {
+        self.trace = Some(HashMap::new());
+    }
+
+    pub fn report_trace(&mut self) {
+        if let Some(trace) = &self.trace {
+            let mut calls: Vec<(&String, &usize)> = trace.iter().collect();
+            calls.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+            _ = writeln!(self.output.0, "-- Calls --");
+            for (name, count) in calls {
+                _ = writeln!(self.output.0, "{count:>8} {name}");
+            }
+        }
+
+        if let Some(timings) = &self.timings {
+            let mut lines: Vec<(&usize, &Duration)> = timings.lines.iter().collect();
+            lines.sort_by_key(|(line, _)| **line);
+
+            _ = writeln!(self.output.0, "-- Lines --");
+            for (line, time) in lines {
+                _ = writeln!(
+                    self.output.0,
+                    "{:>10.3}ms line {line}",
+                    time.as_secs_f64() * 1000.0
+                );
+            }
         }
     }
 
-    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<Literal, ()> {
-        let mut result = Ok(Literal::Nil);
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    pub fn enable_timing(&mut self) {
+        self.timings = Some(Timings::default());
+    }
+
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<Flow, ()> {
+        let mut result = Literal::Nil;
 
         for stmt in statements {
-            match stmt {
-                Stmt::Print { expr } => {
-                    let val = self.evaluate(&expr)?;
+            match self.interpret_stmt(stmt)? {
+                Flow::Normal(value) => result = value,
+                flow => return Ok(flow),
+            }
+        }
+
+        Ok(Flow::Normal(result))
+    }
+
+    fn interpret_stmt(&mut self, stmt: &Stmt) -> Result<Flow, ()> {
+        if let Some(Hook(hook)) = &mut self.hook {
+            let location = stmt.location();
+
+            match hook(stmt, location) {
+                Control::Step | Control::Continue => (),
+                Control::Abort => {
+                    let (line, column) = location.unwrap_or((1, 0));
+                    self.error.report(
+                        (&line, &column),
+                        ErrorType::RuntimeError,
+                        "Execution aborted.",
+                    );
+                    return Err(());
+                }
+            }
+        }
+
+        let line = stmt.location().map(|(line, _)| line);
+        let started = self.timings.is_some().then(|| self.clock.now());
+
+        if let Some(timings) = &mut self.timings {
+            timings.nested.push(Duration::ZERO);
+        }
+
+        let flow = self.execute(stmt);
+
+        if let Some(started) = started
+            && let Some(timings) = &mut self.timings
+        {
+            let elapsed = self.clock.now().saturating_sub(started);
+            let nested = timings.nested.pop().unwrap_or_default();
+
+            if let Some(line) = line {
+                *timings.lines.entry(line).or_default() += elapsed.saturating_sub(nested);
+            }
+
+            if let Some(outer) = timings.nested.last_mut() {
+                *outer += elapsed;
+            }
+        }
+
+        flow
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<Flow, ()> {
+        match stmt {
+            Stmt::Print { expr } => {
+                let val = self.evaluate(expr)?;
+
+                _ = writeln!(self.output.0, "{val}");
+            }
+            Stmt::Var {
+                name,
+                expr,
+                line,
+                column,
+            } => {
+                let val = self.initializer(name, expr, (line, column))?;
+
+                self.environment.borrow_mut().define(name, val);
+            }
+            Stmt::Const {
+                name,
+                expr,
+                line,
+                column,
+            } => {
+                let val = self.initializer(name, expr, (line, column))?;
 
-                    println!("{val}");
+                self.environment.borrow_mut().define_constant(name, val);
+            }
+            Stmt::MultiVar { declarations } => {
+                for declaration in declarations {
+                    self.execute(declaration)?;
                 }
-                Stmt::Var { name, expr } => {
-                    let val = self.evaluate(&expr)?;
+            }
+            Stmt::Block {
+                statements,
+                declares,
+            } => return self.block(statements, *declares),
+            Stmt::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition_val = self.evaluate(condition)?;
 
-                    self.environment.bind(&name, val);
+                let res = if condition_val.is_truthy() {
+                    self.interpret_stmt(then_branch)?
+                } else if let Some(else_branch) = else_branch {
+                    self.interpret_stmt(else_branch)?
+                } else {
+                    Flow::Normal(Literal::Nil)
+                };
+
+                if let flow @ (Flow::Break | Flow::Continue | Flow::Return(..)) = res {
+                    return Ok(flow);
                 }
-                Stmt::Block { statements } => {
-                    self.environment = Environment::new(Some(Box::new(self.environment.clone())));
-                    self.interpret(statements)?;
-                    self.environment = *self.environment.parent.clone().unwrap();
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
+                let enclosing = self.is_loop;
+                self.is_loop = true;
+                let res = self.run_loop(condition, body, increment.as_ref(), false);
+                self.is_loop = enclosing;
+
+                if let Flow::Return(value) = res? {
+                    return Ok(Flow::Return(value));
                 }
-                Stmt::Conditional {
-                    condition,
-                    then_branch,
-                    else_branch,
-                } => {
-                    let condition_val = self.evaluate(&condition)?;
+            }
+            Stmt::DoWhile { body, condition } => {
+                let enclosing = self.is_loop;
+                self.is_loop = true;
+                let res = self.run_loop(condition, body, None, true);
+                self.is_loop = enclosing;
 
-                    if condition_val.is_truthy() {
-                        self.interpret(vec![*then_branch])?;
-                    } else if let Some(else_branch) = else_branch {
-                        self.interpret(vec![*else_branch])?;
-                    }
+                if let Flow::Return(value) = res? {
+                    return Ok(Flow::Return(value));
                 }
-                Stmt::While { condition, body } => {
-                    self.is_loop = true;
-                    while let Ok(condition_val) = self.evaluate(&condition) {
-                        if condition_val.is_truthy() {
-                            if let Err(_) = self.interpret(vec![*body.clone()]) {
-                                break;
-                            }
-                        } else {
-                            break;
+            }
+            Stmt::Break { line, column } => {
+                if self.is_loop {
+                    return Ok(Flow::Break);
+                } else {
+                    self.error.report(
+                        (line, column),
+                        ErrorType::RuntimeError,
+                        "Can not break outside of a loop.",
+                    );
+                    return Err(());
+                }
+            }
+            Stmt::Continue { line, column } => {
+                if self.is_loop {
+                    return Ok(Flow::Continue);
+                } else {
+                    self.error.report(
+                        (line, column),
+                        ErrorType::RuntimeError,
+                        "Can not continue outside of a loop.",
+                    );
+                    return Err(());
+                }
+            }
+            Stmt::Return { expr, .. } => {
+                let val = self.evaluate(expr)?;
+
+                return Ok(Flow::Return(val));
+            }
+            Stmt::Defer { stmt, line, column } => match self.defers.last_mut() {
+                Some(deferred) => deferred.push(*stmt.clone()),
+                None => {
+                    self.error.report(
+                        (line, column),
+                        ErrorType::RuntimeError,
+                        "Can not defer outside of a block or function.",
+                    );
+                    return Err(());
+                }
+            },
+            Stmt::Function {
+                name,
+                params,
+                body,
+                source,
+                ..
+            } => {
+                let func = self.function(
+                    Some(name.clone()),
+                    params.clone(),
+                    body.clone(),
+                    source.clone(),
+                );
+
+                self.environment.borrow_mut().define(name, func);
+            }
+            Stmt::MultiAssign { targets, values } => {
+                let values = values
+                    .iter()
+                    .map(|value| self.evaluate(value))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let names = targets
+                    .iter()
+                    .map(|target| match target {
+                        Expr::Variable { name, id } => self.assignable(name, *id),
+                        target => {
+                            let (line, column) = target.location().unwrap_or((1, 0));
+                            self.error.report(
+                                (&line, &column),
+                                ErrorType::RuntimeError,
+                                "Invalid assignment target.",
+                            );
+                            Err(())
                         }
-                    }
-                    self.is_loop = false;
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if names.len() != values.len() {
+                    let (line, column) = targets[0].location().unwrap_or((1, 0));
+                    self.error.report(
+                        (&line, &column),
+                        ErrorType::RuntimeError,
+                        &format!(
+                            "Expected {} values to assign but got {}.",
+                            names.len(),
+                            values.len()
+                        ),
+                    );
+                    return Err(());
                 }
-                Stmt::Break { line, column } => {
-                    if self.is_loop {
-                        return Err(());
-                    } else {
-                        self.error.report(
-                            (&line, &column),
-                            ErrorType::RuntimeError,
-                            "Can not break outside of a loop.",
-                        );
-                        return Err(());
-                    }
+
+                for ((name, distance), value) in names.into_iter().zip(values) {
+                    self.set_variable(name, distance, value)?;
                 }
-                Stmt::Return { expr } => {
-                    let val = self.evaluate(&expr)?;
+            }
+            Stmt::Expression { expr } => {
+                let literal = self.evaluate(expr)?;
 
-                    return Ok(val);
+                if self.repl {
+                    _ = writeln!(self.output.0, "{literal}");
                 }
-                Stmt::Function { name, params, body } => {
-                    let func = Literal::Callable(Callable::new(
-                        params,
-                        Rc::new(move |interpreter, parameters, args| {
-                            let original_env = interpreter.environment.clone();
 
-                            let mut environment =
-                                Environment::new(Some(Box::new(interpreter.environment.clone())));
+                return Ok(Flow::Normal(literal));
+            }
+        }
 
-                            for (i, param) in parameters.iter().enumerate() {
-                                environment.bind(param, args[i].clone());
-                            }
+        Ok(Flow::Normal(Literal::Nil))
+    }
 
-                            interpreter.environment = environment;
+    fn block(&mut self, statements: &[Stmt], declares: bool) -> Result<Flow, ()> {
+        let previous = self.environment.clone();
 
-                            let res = interpreter.interpret(body.clone());
+        if declares {
+            self.environment = Rc::new(RefCell::new(Environment::new(Some(previous.clone()))));
+        }
+
+        self.defers.push(Vec::new());
+        let res = self.interpret(statements);
+        let deferred = self.run_deferred();
+        self.environment = previous;
+        deferred?;
 
-                            interpreter.environment = original_env.clone();
+        res
+    }
 
-                            res
-                        }),
-                    ));
+    fn initializer(
+        &mut self,
+        name: &str,
+        expr: &Expr,
+        location: (&usize, &usize),
+    ) -> Result<Literal, ()> {
+        let val = if self.lazy {
+            Ok(Thunk::pending(expr.clone(), self.environment.clone()))
+        } else {
+            self.evaluate(expr)
+        };
 
-                    if let Some(name) = name {
-                        self.environment.bind(&name, func);
-                    } else {
-                        return Ok(func);
-                    }
+        val.inspect_err(|_| {
+            self.error.report(
+                location,
+                ErrorType::RuntimeError,
+                &format!("Error while initializing '{name}'."),
+            )
+        })
+    }
+
+    fn run_loop(
+        &mut self,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+        run_first: bool,
+    ) -> Result<Flow, ()> {
+        let mut first = run_first;
+
+        while first || self.evaluate(condition)?.is_truthy() {
+            first = false;
+
+            match self.interpret_stmt(body)? {
+                Flow::Break => break,
+                Flow::Return(value) => return Ok(Flow::Return(value)),
+                Flow::Normal(..) | Flow::Continue => (),
+            }
+
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
+        }
+
+        Ok(Flow::Normal(Literal::Nil))
+    }
+
+    fn function(
+        &self,
+        name: Option<String>,
+        params: Vec<String>,
+        body: Vec<Stmt>,
+        source: String,
+    ) -> Literal {
+        let closure = self.environment.clone();
+
+        Literal::Callable(Callable::new(
+            name,
+            params,
+            source,
+            Rc::new(move |interpreter, _, parameters, args| {
+                let mut environment = Environment::new(Some(closure.clone()));
+
+                for (param, arg) in parameters.iter().zip(args) {
+                    environment.values.insert(param.to_owned(), arg);
                 }
-                Stmt::Expression { expr } => {
-                    let literal = self.evaluate(&expr)?;
 
-                    if self.repl {
-                        println!("{literal}");
-                    }
+                let original_env = std::mem::replace(
+                    &mut interpreter.environment,
+                    Rc::new(RefCell::new(environment)),
+                );
+
+                let is_loop = interpreter.is_loop;
+                interpreter.is_loop = false;
+
+                interpreter.defers.push(Vec::new());
+                let res = interpreter.interpret(&body);
+                let deferred = interpreter.run_deferred();
 
-                    result = Ok(literal);
+                interpreter.is_loop = is_loop;
+                interpreter.environment = original_env;
+                deferred?;
+
+                match res? {
+                    Flow::Return(value) => Ok(value),
+                    _ => Ok(Literal::Nil),
                 }
+            }),
+        ))
+    }
+
+    // Deferred statements run last-in first-out, even when the scope is left
+    // through an error, and all of them run before any failure is reported.
+    fn run_deferred(&mut self) -> Result<(), ()> {
+        let deferred = self.defers.pop().unwrap_or_default();
+        let mut res = Ok(());
+
+        for stmt in deferred.into_iter().rev() {
+            if self.interpret_stmt(&stmt).is_err() {
+                res = Err(());
             }
         }
 
-        result
+        res
+    }
+
+    // Arithmetic on finite numbers can still overflow, and neither NaN nor
+    // infinity is a value Lox programs can observe.
+    fn number(&self, value: f64, location: (&usize, &usize), symbol: &str) -> Result<Literal, ()> {
+        if value.is_finite() {
+            Ok(Literal::Number(value))
+        } else {
+            self.error.report(
+                location,
+                ErrorType::RuntimeError,
+                &format!("Result of '{symbol}' is too large to represent."),
+            );
+            Err(())
+        }
+    }
+
+    fn key(&self, value: &Literal, token: &Token) -> Result<Key, ()> {
+        Key::from_literal(value).map_err(|message| {
+            self.error
+                .report(token.location(), ErrorType::RuntimeError, &message);
+        })
+    }
+
+    fn list_index(&self, index: &Literal, length: usize, bracket: &Token) -> Result<usize, ()> {
+        let message = match index {
+            Literal::Number(n) if n.fract() == 0.0 && *n >= 0.0 && (*n as usize) < length => {
+                return Ok(*n as usize);
+            }
+            Literal::Number(n) if n.fract() == 0.0 => {
+                format!("List index {index} is out of range for a list of length {length}.")
+            }
+            index => format!("List index must be a whole number but got {index}."),
+        };
+
+        self.error
+            .report(bracket.location(), ErrorType::RuntimeError, &message);
+        Err(())
+    }
+
+    fn lookup(&self, id: usize, name: &str) -> Option<Literal> {
+        match self.locals.get(&id) {
+            Some(distance) => self.environment.borrow().get_at(*distance, name),
+            None => self.globals.borrow().get(name),
+        }
+    }
+
+    fn set_variable(
+        &mut self,
+        name: &str,
+        distance: Option<usize>,
+        value: Literal,
+    ) -> Result<(), ()> {
+        match distance {
+            Some(distance) => self
+                .environment
+                .borrow_mut()
+                .assign_at(distance, name, value),
+            None => self.globals.borrow_mut().assign(name, value),
+        }
+    }
+
+    fn assignable<'a>(&self, name: &'a Token, id: usize) -> Result<(&'a str, Option<usize>), ()> {
+        match name {
+            Token::Identifier {
+                value: name,
+                line,
+                column,
+            } => {
+                let distance = self.locals.get(&id).copied();
+
+                if distance.is_none() && self.globals.borrow().is_builtin(name) {
+                    self.error.report_span(
+                        (line, column),
+                        name.chars().count(),
+                        ErrorType::RuntimeError,
+                        &format!("Cannot reassign builtin '{}'", name),
+                    );
+                    return Err(());
+                }
+
+                let constant = match distance {
+                    Some(distance) => self.environment.borrow().is_constant_at(distance, name),
+                    None => self.globals.borrow().is_constant(name),
+                };
+
+                if constant {
+                    self.error.report_span(
+                        (line, column),
+                        name.chars().count(),
+                        ErrorType::RuntimeError,
+                        &format!("Cannot assign to constant '{}'", name),
+                    );
+                    return Err(());
+                }
+
+                if self.lookup(id, name).is_some() {
+                    Ok((name, distance))
+                } else {
+                    self.error.report_span(
+                        (line, column),
+                        name.chars().count(),
+                        ErrorType::RuntimeError,
+                        &format!("Undefined variable '{}'", name),
+                    );
+                    Err(())
+                }
+            }
+            token => {
+                self.error.report(
+                    token.location(),
+                    ErrorType::RuntimeError,
+                    "Invalid identifier.",
+                );
+                Err(())
+            }
+        }
     }
 
     pub fn evaluate(&mut self, expr: &Expr) -> Result<Literal, ()> {
         match expr {
             Expr::Literal { value } => Ok(value.clone()),
+            Expr::Lambda {
+                params,
+                body,
+                source,
+                ..
+            } => Ok(self.function(None, params.clone(), body.clone(), source.clone())),
             Expr::Unary { operator, right } => match operator {
                 Token::Minus { line, column } => match self.evaluate(right) {
                     Ok(Literal::Number(number)) => Ok(Literal::Number(-number)),
+                    Ok(Literal::Nil) => {
+                        self.error.report(
+                            (line, column),
+                            ErrorType::RuntimeError,
+                            "Cannot apply '-' to nil.",
+                        );
+                        Err(())
+                    }
                     Ok(_) => {
                         self.error.report(
                             (line, column),
@@ -182,7 +805,14 @@ impl Interpreter<'_> {
                     Ok(_) => Ok(Literal::Boolean(false)),
                     Err(_) => Err(()),
                 },
-                _ => unreachable!(),
+                token => {
+                    self.error.report(
+                        token.location(),
+                        ErrorType::RuntimeError,
+                        "Invalid operator.",
+                    );
+                    Err(())
+                }
             },
             Expr::Binary {
                 left,
@@ -192,10 +822,31 @@ impl Interpreter<'_> {
                 let left = self.evaluate(left)?;
                 let right = self.evaluate(right)?;
 
+                if matches!(
+                    operator,
+                    Token::Minus { .. }
+                        | Token::Plus { .. }
+                        | Token::Slash { .. }
+                        | Token::Star { .. }
+                        | Token::Ampersand { .. }
+                        | Token::Pipe { .. }
+                        | Token::Caret { .. }
+                        | Token::LessLess { .. }
+                        | Token::GreaterGreater { .. }
+                ) && (matches!(left, Literal::Nil) || matches!(right, Literal::Nil))
+                {
+                    self.error.report(
+                        operator.location(),
+                        ErrorType::RuntimeError,
+                        &format!("Cannot apply '{}' to nil.", operator.symbol()),
+                    );
+                    return Err(());
+                }
+
                 match operator {
                     Token::Minus { line, column } => match (left, right) {
                         (Literal::Number(left), Literal::Number(right)) => {
-                            Ok(Literal::Number(left - right))
+                            self.number(left - right, (line, column), "-")
                         }
                         (_, _) => {
                             self.error.report(
@@ -208,16 +859,16 @@ impl Interpreter<'_> {
                     },
                     Token::Plus { line, column } => match (left, right) {
                         (Literal::Number(left), Literal::Number(right)) => {
-                            Ok(Literal::Number(left + right))
+                            self.number(left + right, (line, column), "+")
                         }
                         (Literal::Number(left), Literal::String(right)) => {
-                            Ok(Literal::String(left.to_string() + &right))
+                            Ok(Literal::String(Literal::Number(left).to_string() + &right))
                         }
                         (Literal::String(left), Literal::String(right)) => {
                             Ok(Literal::String(left.to_owned() + &right))
                         }
                         (Literal::String(left), Literal::Number(right)) => {
-                            Ok(Literal::String(left.to_owned() + &right.to_string()))
+                            Ok(Literal::String(left + &Literal::Number(right).to_string()))
                         }
                         (_, _) => {
                             self.error.report(
@@ -230,7 +881,7 @@ impl Interpreter<'_> {
                     },
                     Token::Slash { line, column } => match (left, right) {
                         (Literal::Number(left), Literal::Number(right)) => {
-                            if left == 0.0 && right == 0.0 {
+                            if right == 0.0 {
                                 self.error.report(
                                     (line, column),
                                     ErrorType::RuntimeError,
@@ -238,7 +889,7 @@ impl Interpreter<'_> {
                                 );
                                 Err(())
                             } else {
-                                Ok(Literal::Number(left / right))
+                                self.number(left / right, (line, column), "/")
                             }
                         }
                         (_, _) => {
@@ -252,7 +903,7 @@ impl Interpreter<'_> {
                     },
                     Token::Star { line, column } => match (left, right) {
                         (Literal::Number(left), Literal::Number(right)) => {
-                            Ok(Literal::Number(left * right))
+                            self.number(left * right, (line, column), "*")
                         }
                         (_, _) => {
                             self.error.report(
@@ -267,11 +918,15 @@ impl Interpreter<'_> {
                         (Literal::Number(left), Literal::Number(right)) => {
                             Ok(Literal::Boolean(left > right))
                         }
+                        // Booleans order like 0 and 1, so false < true.
+                        (Literal::Boolean(left), Literal::Boolean(right)) => {
+                            Ok(Literal::Boolean(left.cmp(&right).is_gt()))
+                        }
                         (_, _) => {
                             self.error.report(
                                 (line, column),
                                 ErrorType::RuntimeError,
-                                "Operator '>' can only be applied to numbers",
+                                "Operator '>' can only be applied to two numbers or two booleans",
                             );
                             Err(())
                         }
@@ -280,11 +935,14 @@ impl Interpreter<'_> {
                         (Literal::Number(left), Literal::Number(right)) => {
                             Ok(Literal::Boolean(left >= right))
                         }
+                        (Literal::Boolean(left), Literal::Boolean(right)) => {
+                            Ok(Literal::Boolean(left.cmp(&right).is_ge()))
+                        }
                         (_, _) => {
                             self.error.report(
                                 (line, column),
                                 ErrorType::RuntimeError,
-                                "Operator '>' can only be applied to numbers",
+                                "Operator '>=' can only be applied to two numbers or two booleans",
                             );
                             Err(())
                         }
@@ -293,11 +951,14 @@ impl Interpreter<'_> {
                         (Literal::Number(left), Literal::Number(right)) => {
                             Ok(Literal::Boolean(left < right))
                         }
+                        (Literal::Boolean(left), Literal::Boolean(right)) => {
+                            Ok(Literal::Boolean(left.cmp(&right).is_lt()))
+                        }
                         (_, _) => {
                             self.error.report(
                                 (line, column),
                                 ErrorType::RuntimeError,
-                                "Operator '<' can only be applied to numbers",
+                                "Operator '<' can only be applied to two numbers or two booleans",
                             );
                             Err(())
                         }
@@ -306,11 +967,55 @@ impl Interpreter<'_> {
                         (Literal::Number(left), Literal::Number(right)) => {
                             Ok(Literal::Boolean(left <= right))
                         }
+                        (Literal::Boolean(left), Literal::Boolean(right)) => {
+                            Ok(Literal::Boolean(left.cmp(&right).is_le()))
+                        }
+                        (_, _) => {
+                            self.error.report(
+                                (line, column),
+                                ErrorType::RuntimeError,
+                                "Operator '<=' can only be applied to two numbers or two booleans",
+                            );
+                            Err(())
+                        }
+                    },
+                    Token::Ampersand { line, column }
+                    | Token::Pipe { line, column }
+                    | Token::Caret { line, column }
+                    | Token::LessLess { line, column }
+                    | Token::GreaterGreater { line, column } => match (left, right) {
+                        (Literal::Number(left), Literal::Number(right)) => {
+                            let (left, right) = (left as i64, right as i64);
+                            let shift = u32::try_from(right).ok().filter(|shift| *shift < 64);
+
+                            let value = match operator {
+                                Token::Ampersand { .. } => Some(left & right),
+                                Token::Pipe { .. } => Some(left | right),
+                                Token::Caret { .. } => Some(left ^ right),
+                                Token::LessLess { .. } => shift.map(|shift| left << shift),
+                                _ => shift.map(|shift| left >> shift),
+                            };
+
+                            match value {
+                                Some(value) => Ok(Literal::Number(value as f64)),
+                                None => {
+                                    self.error.report(
+                                        (line, column),
+                                        ErrorType::RuntimeError,
+                                        "Shift amount must be between 0 and 63",
+                                    );
+                                    Err(())
+                                }
+                            }
+                        }
                         (_, _) => {
                             self.error.report(
                                 (line, column),
                                 ErrorType::RuntimeError,
-                                "Operator '<' can only be applied to numbers",
+                                &format!(
+                                    "Operator '{}' can only be applied to numbers",
+                                    operator.symbol()
+                                ),
                             );
                             Err(())
                         }
@@ -372,72 +1077,167 @@ impl Interpreter<'_> {
                 }
             }
             Expr::Grouping { expression } => self.evaluate(expression),
-            Expr::Variable { name } => match name {
+            Expr::Variable { name, id } => match name {
                 Token::Identifier {
                     value,
                     line,
                     column,
-                } => match self.environment.get(&value) {
-                    Some(value) => Ok(value.clone()),
-                    None => {
-                        self.error.report(
-                            (line, column),
-                            ErrorType::RuntimeError,
-                            &format!("Undefined variable '{}'", value),
-                        );
-                        Err(())
-                    }
-                },
-                _ => unreachable!(),
-            },
-            Expr::Assign { name, value } => {
-                let value = self.evaluate(value)?;
+                } => {
+                    let found = self.lookup(*id, value);
 
-                match name {
-                    Token::Identifier {
-                        value: name,
-                        line,
-                        column,
-                    } => {
-                        if let Some(_) = self.environment.get(&name) {
-                            self.environment.bind(&name, value.clone());
-                            Ok(value)
-                        } else {
-                            self.error.report(
+                    match found {
+                        Some(value) => self.force(value, (line, column)),
+                        None => {
+                            self.error.report_span(
                                 (line, column),
+                                name.width(),
                                 ErrorType::RuntimeError,
-                                &format!("Undefined variable '{}'", name),
+                                &format!("Undefined variable '{}'", value),
                             );
                             Err(())
                         }
                     }
-                    _ => unreachable!(),
                 }
+                token => {
+                    self.error.report(
+                        token.location(),
+                        ErrorType::RuntimeError,
+                        "Invalid identifier.",
+                    );
+                    Err(())
+                }
+            },
+            Expr::Map { entries, brace } => {
+                let mut map = HashMap::new();
+
+                for (key, value) in entries {
+                    let key = self.evaluate(key)?;
+                    let key = self.key(&key, brace)?;
+                    map.insert(key, self.evaluate(value)?);
+                }
+
+                Ok(Literal::Map(Rc::new(RefCell::new(map))))
             }
-            Expr::Logical {
-                left,
-                operator,
-                right,
+            Expr::Block {
+                statements,
+                declares,
+                brace,
             } => {
-                let left = self.evaluate(left)?;
-                let right = self.evaluate(right)?;
+                // Only the block's value is echoed in the REPL, not its statements.
+                let repl = std::mem::replace(&mut self.repl, false);
+                let flow = self.block(statements, *declares);
+                self.repl = repl;
 
-                match operator {
-                    Token::Or { .. } => {
-                        if left.is_truthy() {
-                            Ok(left)
-                        } else {
-                            Ok(right)
-                        }
+                match flow? {
+                    Flow::Normal(value) => Ok(value),
+                    _ => {
+                        self.error.report(
+                            brace.location(),
+                            ErrorType::RuntimeError,
+                            "Can not break, continue or return out of a block expression.",
+                        );
+                        Err(())
                     }
-                    Token::And { .. } => {
-                        if left.is_truthy() {
-                            Ok(right)
-                        } else {
-                            Ok(left)
-                        }
+                }
+            }
+            Expr::Index {
+                object,
+                index,
+                bracket,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+
+                match &object {
+                    Literal::List(list) => {
+                        let i = self.list_index(&index, list.borrow().len(), bracket)?;
+                        Ok(list.borrow()[i].clone())
+                    }
+                    Literal::Map(map) => {
+                        let key = self.key(&index, bracket)?;
+                        Ok(map.borrow().get(&key).cloned().unwrap_or(Literal::Nil))
+                    }
+                    value => {
+                        self.error.report(
+                            bracket.location(),
+                            ErrorType::RuntimeError,
+                            &format!(
+                                "Can only index lists and maps but got {}.",
+                                value.type_name()
+                            ),
+                        );
+                        Err(())
+                    }
+                }
+            }
+            Expr::Set {
+                object,
+                index,
+                value,
+                bracket,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let value = self.evaluate(value)?;
+
+                match &object {
+                    Literal::List(list) => {
+                        let i = self.list_index(&index, list.borrow().len(), bracket)?;
+                        list.borrow_mut()[i] = value.clone();
+                    }
+                    Literal::Map(map) => {
+                        let key = self.key(&index, bracket)?;
+                        map.borrow_mut().insert(key, value.clone());
+                    }
+                    value => {
+                        self.error.report(
+                            bracket.location(),
+                            ErrorType::RuntimeError,
+                            &format!(
+                                "Can only index lists and maps but got {}.",
+                                value.type_name()
+                            ),
+                        );
+                        return Err(());
+                    }
+                }
+
+                Ok(value)
+            }
+            Expr::Assign { name, value, id } => {
+                let value = self.evaluate(value)?;
+                let (name, distance) = self.assignable(name, *id)?;
+
+                self.set_variable(name, distance, value.clone())?;
+                Ok(value)
+            }
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.evaluate(left)?;
+
+                // The right operand is only evaluated when the left one
+                // doesn't already decide the result.
+                let decided = match operator {
+                    Token::Or { .. } => left.is_truthy(),
+                    Token::And { .. } => !left.is_truthy(),
+                    Token::QuestionQuestion { .. } => !matches!(left, Literal::Nil),
+                    token => {
+                        self.error.report(
+                            token.location(),
+                            ErrorType::RuntimeError,
+                            "Invalid operator.",
+                        );
+                        return Err(());
                     }
-                    _ => unreachable!(),
+                };
+
+                if decided {
+                    Ok(left)
+                } else {
+                    self.evaluate(right)
                 }
             }
             Expr::Call {
@@ -445,27 +1245,62 @@ impl Interpreter<'_> {
                 paren,
                 arguments,
             } => {
+                let name = match callee.as_ref() {
+                    Expr::Variable {
+                        name: Token::Identifier { value, .. },
+                        ..
+                    } => value.as_str(),
+                    _ => "<anonymous>",
+                };
+
                 let callee = self.evaluate(callee)?;
 
                 match callee {
                     Literal::Callable(callable) => {
                         let mut evaluated_arguments = Vec::new();
                         for argument in arguments {
-                            evaluated_arguments.push(self.interpret(vec![argument.clone()])?);
+                            evaluated_arguments.push(if self.lazy {
+                                Thunk::pending(argument.clone(), self.environment.clone())
+                            } else {
+                                self.evaluate(argument)?
+                            });
                         }
 
                         let actual = evaluated_arguments.len();
                         let expected = callable.arity();
 
-                        if actual != expected {
+                        if !expected.accepts(actual) {
+                            let message = match callable.name() {
+                                Some(name) => format!(
+                                    "Expected {} arguments to '{}' but got {}.",
+                                    expected, name, actual
+                                ),
+                                None => {
+                                    format!("Expected {} arguments but got {}.", expected, actual)
+                                }
+                            };
+
+                            self.error
+                                .report(paren.location(), ErrorType::RuntimeError, &message);
+                            Err(())
+                        } else if self.depth >= self.max_depth {
                             self.error.report(
                                 paren.location(),
                                 ErrorType::RuntimeError,
-                                &format!("Expected {} arguments but got {}.", expected, actual),
+                                "Stack overflow.",
                             );
                             Err(())
                         } else {
-                            callable.call(self, evaluated_arguments)
+                            if let Some(trace) = &mut self.trace {
+                                let name = callable.name().unwrap_or(name);
+                                *trace.entry(name.to_owned()).or_insert(0) += 1;
+                            }
+
+                            self.depth += 1;
+                            let result = callable.call(self, paren, evaluated_arguments);
+                            self.depth -= 1;
+
+                            result
                         }
                     }
                     _ => {
@@ -481,3 +1316,828 @@ impl Interpreter<'_> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::runner::Options;
+    use crate::testing;
+
+    #[test]
+    fn outer_variables_mutated_in_a_block_stay_changed() {
+        let output = testing::run(
+            "var a = 1;
+            { var b = 2; a = a + b; { var c = 3; a = a * c; } }
+            print a;
+            { a = 0; }
+            print a;",
+        );
+
+        assert_eq!(output, "9\n0\n");
+    }
+
+    #[test]
+    fn shadowing_in_a_block_leaves_the_outer_variable_alone() {
+        let output = testing::run("var a = 1; { var a = 2; a = 3; } print a;");
+
+        assert_eq!(output, "1\n");
+    }
+
+    #[test]
+    fn concatenation_formats_numbers_like_print() {
+        let output = testing::run(
+            "print \"\" + 1_000_000_000_000_000_000_000;
+            print 1 / 10_000_000 + \"\";
+            print \"n\" + 2.5;",
+        );
+
+        assert_eq!(output, "1e21\n1e-7\nn2.5\n");
+    }
+
+    #[test]
+    fn continue_skips_to_the_next_iteration() {
+        let output = testing::run(
+            "var i = 0;
+            while (i < 3) { i = i + 1; if (i == 2) continue; print i; }",
+        );
+
+        assert_eq!(output, "1\n3\n");
+    }
+
+    #[test]
+    fn continue_in_a_for_loop_still_runs_the_increment() {
+        let output =
+            testing::run("for (var i = 0; i < 4; i = i + 1) { if (i == 1) continue; print i; }");
+
+        assert_eq!(output, "0\n2\n3\n");
+    }
+
+    #[test]
+    fn continue_only_affects_the_innermost_loop() {
+        let output = testing::run(
+            "for (var i = 0; i < 2; i = i + 1)
+                for (var j = 0; j < 2; j = j + 1) { if (j == 0) continue; print i + j; }",
+        );
+
+        assert_eq!(output, "1\n2\n");
+    }
+
+    #[test]
+    fn continue_outside_a_loop_is_an_error() {
+        let output = testing::run("fun f() { continue; } while (true) { f(); }");
+
+        assert!(
+            output.contains("Can not continue outside of a loop."),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn return_leaves_nested_loops_and_blocks() {
+        let output = testing::run(
+            "fun f() { while (true) { for (var i = 0; ; i = i + 1) { { if (i == 2) return i; } } } }
+            print f();",
+        );
+
+        assert_eq!(output, "2\n");
+    }
+
+    #[test]
+    fn break_only_leaves_the_innermost_loop() {
+        let output =
+            testing::run("for (var i = 0; i < 2; i = i + 1) { while (true) { break; } print i; }");
+
+        assert_eq!(output, "0\n1\n");
+    }
+
+    #[test]
+    fn break_inside_a_called_function_does_not_leave_the_callers_loop() {
+        let output = testing::run("fun f() { break; } while (true) f();");
+
+        assert!(
+            output.contains("Can not break outside of a loop."),
+            "{output}"
+        );
+    }
+
+    fn traced(source: &str) -> String {
+        let options = Options {
+            trace: true,
+            ..Options::default()
+        };
+
+        testing::run_with(source, &options, |_| ()).1
+    }
+
+    #[test]
+    fn trace_counts_calls_busiest_first() {
+        let output = traced("fun f() {} fun g() { f(); } g(); g(); f(); clock();");
+
+        assert_eq!(
+            output,
+            "-- Calls --\n       3 f\n       2 g\n       1 clock\n"
+        );
+    }
+
+    #[test]
+    fn trace_breaks_ties_by_name() {
+        let output = traced("fun b() {} fun a() {} b(); a();");
+
+        assert_eq!(output, "-- Calls --\n       1 a\n       1 b\n");
+    }
+
+    #[test]
+    fn trace_counts_lambdas_under_their_display_name() {
+        let output = traced("var f = fun () {}; f(); f();");
+
+        assert!(output.starts_with("-- Calls --\n       2 "), "{output}");
+    }
+
+    #[test]
+    fn arithmetic_on_nil_names_the_operator() {
+        for (source, operator) in [
+            ("print nil + 1;", "+"),
+            ("print 1 - nil;", "-"),
+            ("print -nil;", "-"),
+            ("print nil * 2;", "*"),
+            ("print 4 / nil;", "/"),
+            ("print \"a\" + nil;", "+"),
+        ] {
+            let output = testing::run(source);
+
+            assert!(
+                output.contains(&format!("RuntimeError: Cannot apply '{operator}' to nil.")),
+                "{source}: {output}"
+            );
+        }
+    }
+
+    #[test]
+    fn arithmetic_on_nil_points_at_the_operator() {
+        let output = testing::run("print 1 + nil;");
+
+        assert!(
+            output.starts_with("print 1 + nil;\n        ^ -- Here\n"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn failed_initializers_point_at_the_declaration() {
+        let output = testing::run("var a = nil + 1;");
+        let expected = "var a = nil + 1;\n    ^ -- Here\n\
+            <test> @ Line 1 - RuntimeError: Error while initializing 'a'.\n";
+
+        assert!(output.ends_with(expected), "{output}");
+    }
+
+    #[test]
+    fn failed_initializers_name_the_variable_in_every_declaration_form() {
+        for (source, name) in [("const b = -\"x\";", "b"), ("var a = 1, c = nil * 2;", "c")] {
+            let output = testing::run(source);
+
+            assert!(
+                output.contains(&format!("Error while initializing '{name}'.")),
+                "{output}"
+            );
+        }
+    }
+
+    #[test]
+    fn closures_keep_their_own_captured_state() {
+        let output = testing::run(
+            "fun counter() { var n = 0; fun inc() { n = n + 1; return n; } return inc; }
+            var a = counter(); var b = counter();
+            a(); a(); print a(); print b();",
+        );
+
+        assert_eq!(output, "3\n1\n");
+    }
+
+    #[test]
+    fn closures_see_their_defining_scope_rather_than_the_callers() {
+        let output = testing::run(
+            "var x = \"global\";
+            fun show() { print x; }
+            fun caller() { var x = \"local\"; show(); }
+            caller();",
+        );
+
+        assert_eq!(output, "global\n");
+    }
+
+    #[test]
+    fn closures_bind_the_variable_in_scope_when_they_were_defined() {
+        let output = testing::run(
+            "var a = \"outer\";
+            { fun show() { print a; } show(); var a = \"inner\"; show(); }",
+        );
+
+        assert_eq!(output, "outer\nouter\n");
+    }
+
+    fn lazily(source: &str) -> String {
+        let options = Options {
+            lazy: true,
+            ..Options::default()
+        };
+
+        testing::run_with(source, &options, |_| ()).1
+    }
+
+    #[test]
+    fn lazy_initializers_run_on_first_use_and_only_once() {
+        let output = lazily(
+            "fun boom() { print \"evaluated\"; return 1; }
+            var a = boom(); print \"before\"; print a; print a;",
+        );
+
+        assert_eq!(output, "before\nevaluated\n1\n1\n");
+    }
+
+    #[test]
+    fn lazy_initializers_that_are_never_used_never_run() {
+        assert_eq!(lazily("var a = nil + 1; print \"ok\";"), "ok\n");
+    }
+
+    #[test]
+    fn lazy_initializers_see_the_variables_as_they_are_when_forced() {
+        assert_eq!(lazily("var a = 1; var b = a; a = 2; print b;"), "2\n");
+    }
+
+    #[test]
+    fn do_while_runs_its_body_before_checking() {
+        let output = testing::run("var i = 5; do { print i; i = i + 1; } while (i < 3);");
+
+        assert_eq!(output, "5\n");
+    }
+
+    #[test]
+    fn do_while_supports_break_and_continue() {
+        let output = testing::run(
+            "var i = 0;
+            do { i = i + 1; if (i == 2) continue; if (i == 4) break; print i; } while (true);",
+        );
+
+        assert_eq!(output, "1\n3\n");
+    }
+
+    #[test]
+    fn anonymous_functions_are_values() {
+        let output = testing::run(
+            "fun apply(f, x) { return f(x); }
+            print apply(fun (n) { return n * 2; }, 4);
+            print (fun () => 7)();
+            print fun (a) {};",
+        );
+
+        assert_eq!(output, "8\n7\n<fn>\n");
+    }
+
+    #[test]
+    fn slash_equal_divides_in_place() {
+        assert_eq!(testing::run("var a = 9; a /= 3; print a;"), "3\n");
+    }
+
+    #[test]
+    fn slash_equal_reports_division_errors() {
+        assert!(testing::run("var a = 9; a /= 0;").contains("Can not divide by 0"));
+        assert!(
+            testing::run("var a = \"x\"; a /= 2;")
+                .contains("Operator '/' can only be applied to numbers")
+        );
+    }
+
+    #[test]
+    fn plus_equal_concatenates_strings() {
+        assert_eq!(
+            testing::run("var s = \"a\"; s += \"b\"; s += 1; print s;"),
+            "ab1\n"
+        );
+    }
+
+    #[test]
+    fn compound_assignments_update_in_place() {
+        assert_eq!(
+            testing::run("var n = 2; n *= 3; n -= 1; n += 10; print n;"),
+            "15\n"
+        );
+    }
+
+    #[test]
+    fn compound_assignments_update_list_and_map_items() {
+        assert_eq!(
+            testing::run(
+                "var l = split(\"a,b\", \",\"); l[0] += \"x\"; print l; \
+                 var m = {\"n\": 2}; m[\"n\"] *= 5; print m[\"n\"]; print m[\"n\"] -= 3;"
+            ),
+            "[ax, b]\n10\n7\n"
+        );
+    }
+
+    #[test]
+    fn compound_assignment_evaluates_the_object_and_index_once() {
+        assert_eq!(
+            testing::run(
+                "var calls = 0; var l = range(0, 3); \
+                 fun at() { calls += 1; return 1; } \
+                 fun list() { calls += 1; return l; } \
+                 list()[at()] += 10; print l; print calls;"
+            ),
+            "[0, 11, 2]\n2\n"
+        );
+    }
+
+    #[test]
+    fn simultaneous_assignment_swaps_values() {
+        assert_eq!(
+            testing::run("var a = 1; var b = 2; a, b = b, a; print a; print b;"),
+            "2\n1\n"
+        );
+        assert_eq!(
+            testing::run(
+                "var a = 1; var b = 2; var c = 3; a, b, c = c, a, b; print a + b * 10 + c * 100;"
+            ),
+            "213\n"
+        );
+    }
+
+    #[test]
+    fn simultaneous_assignment_reports_a_count_mismatch() {
+        let output = testing::run("var a = 1; var b = 2; a, b = 3; print a;");
+
+        assert!(output.contains("RuntimeError: Expected 2 values to assign but got 1."));
+        assert!(!output.starts_with('3'));
+    }
+
+    #[test]
+    fn assigning_an_undeclared_variable_is_an_error() {
+        assert!(testing::run("x = 1;").contains("RuntimeError: Undefined variable 'x'"));
+        assert!(testing::run("{ y = 2; } print y;").contains("Undefined variable 'y'"));
+    }
+
+    #[test]
+    fn assignment_updates_the_declared_variable() {
+        assert_eq!(testing::run("var z; { z = 3; } print z;"), "3\n");
+    }
+
+    #[test]
+    fn variable_errors_underline_the_whole_name() {
+        assert_eq!(
+            testing::run("print foobar;"),
+            "print foobar;\n      ^^^^^^ -- Here\n\
+             <test> @ Line 1 - RuntimeError: Undefined variable 'foobar'\n"
+        );
+        assert!(testing::run("foobar = 1;").starts_with("foobar = 1;\n^^^^^^ -- Here\n"));
+        assert!(testing::run("clock = 1;").starts_with("clock = 1;\n^^^^^ -- Here\n"));
+        assert!(testing::run("const kk = 2; kk = 3;").contains("\n              ^^ -- Here\n"));
+    }
+
+    #[test]
+    fn bitwise_operators_work_on_truncated_integers() {
+        assert_eq!(
+            testing::run(
+                "print 6 & 3; print 6 | 3; print 6 ^ 3; print 1 << 4; print 256 >> 2; \
+                 print 5.9 & 7; print -8 >> 1;"
+            ),
+            "2\n7\n5\n16\n64\n5\n-4\n"
+        );
+    }
+
+    #[test]
+    fn bitwise_operators_reject_non_numbers() {
+        assert!(
+            testing::run("print \"a\" & 1;")
+                .contains("RuntimeError: Operator '&' can only be applied to numbers")
+        );
+    }
+
+    #[test]
+    fn bitwise_operators_on_nil_name_the_operator() {
+        for (source, operator) in [
+            ("print nil & 1;", "&"),
+            ("print 1 | nil;", "|"),
+            ("print nil ^ nil;", "^"),
+            ("print nil << 1;", "<<"),
+            ("print 8 >> nil;", ">>"),
+        ] {
+            let output = testing::run(source);
+
+            assert!(
+                output.contains(&format!("RuntimeError: Cannot apply '{operator}' to nil.")),
+                "{source}: {output}"
+            );
+        }
+    }
+
+    #[test]
+    fn top_level_functions_can_call_each_other_in_any_order() {
+        assert_eq!(
+            testing::run(
+                "print even(4); \
+                 fun even(n) { if (n == 0) return true; return odd(n - 1); } \
+                 fun odd(n) { if (n == 0) return false; return even(n - 1); } \
+                 print odd(3);"
+            ),
+            "true\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn booleans_order_false_before_true() {
+        assert_eq!(
+            testing::run(
+                "print true > false; print false < true; print true >= true; \
+                 print false <= false; print true < true;"
+            ),
+            "true\ntrue\ntrue\ntrue\nfalse\n"
+        );
+    }
+
+    #[test]
+    fn booleans_do_not_compare_with_other_types() {
+        assert!(testing::run("print true > 0;").contains(
+            "RuntimeError: Operator '>' can only be applied to two numbers or two booleans"
+        ));
+    }
+
+    #[test]
+    fn callables_display_their_name() {
+        assert_eq!(
+            testing::run("fun add(a, b) {} print add; print clock; print fun () {};"),
+            "<fn add>\n<fn clock>\n<fn>\n"
+        );
+    }
+
+    #[test]
+    fn arity_errors_name_the_callable() {
+        assert!(
+            testing::run("fun add(a, b) {} add(1);")
+                .contains("RuntimeError: Expected 2 arguments to 'add' but got 1.")
+        );
+        assert!(testing::run("clock(1);").contains("Expected 0 arguments to 'clock' but got 1."));
+        assert!(
+            testing::run("var f = fun (a) {}; f();").contains("Expected 1 arguments but got 0.")
+        );
+    }
+
+    #[test]
+    fn traces_count_calls_under_the_function_name() {
+        assert_eq!(
+            traced("fun f() {} f(); var g = f; g(); var h = fun () {}; h();"),
+            "-- Calls --\n       2 f\n       1 h\n"
+        );
+    }
+
+    fn with_depth(max_depth: usize, source: &str) -> (i32, String) {
+        let options = Options {
+            max_depth,
+            ..Options::default()
+        };
+
+        testing::run_with(source, &options, |_| ())
+    }
+
+    #[test]
+    fn unbounded_recursion_is_a_stack_overflow_error() {
+        let (status, output) = with_depth(5, "fun f() { return f(); } f();");
+
+        assert_eq!(status, 70);
+        assert!(output.contains("RuntimeError: Stack overflow."));
+    }
+
+    #[test]
+    fn recursion_up_to_the_limit_runs() {
+        let source = "fun f(n) { if (n < 5) return f(n + 1); return n; } print f(1);";
+
+        assert_eq!(with_depth(5, source), (0, "5\n".to_owned()));
+        assert_eq!(with_depth(4, source).0, 70);
+    }
+
+    #[test]
+    fn defer_runs_when_the_block_exits() {
+        assert_eq!(
+            testing::run("{ defer print \"a\"; print \"b\"; } print \"c\";"),
+            "b\na\nc\n"
+        );
+        assert_eq!(
+            testing::run("for (var i = 0; i < 2; i = i + 1) { defer print i; print \"it\"; }"),
+            "it\n0\nit\n1\n"
+        );
+    }
+
+    #[test]
+    fn defers_run_in_reverse_order_on_return() {
+        assert_eq!(
+            testing::run(
+                "fun f() { defer print \"d1\"; defer print \"d2\"; print \"body\"; return 1; } \
+                 print f();"
+            ),
+            "body\nd2\nd1\n1\n"
+        );
+    }
+
+    #[test]
+    fn defer_runs_when_an_error_unwinds() {
+        let output = testing::run("fun f() { defer print \"cleanup\"; var x = nil + 1; } f();");
+
+        assert!(output.ends_with("cleanup\n"), "{output}");
+    }
+
+    #[test]
+    fn defer_needs_an_enclosing_block() {
+        assert!(
+            testing::run("defer print \"top\";")
+                .contains("Can not defer outside of a block or function.")
+        );
+    }
+
+    #[test]
+    fn division_by_zero_never_yields_nan_or_infinity() {
+        for source in ["print 0 / 0;", "print 1 / 0;", "print -1 / 0;"] {
+            let output = testing::run(source);
+
+            assert!(
+                output.contains("RuntimeError: Can not divide by 0"),
+                "{output}"
+            );
+            assert!(
+                !output.contains("NaN") && !output.contains("inf"),
+                "{output}"
+            );
+        }
+    }
+
+    #[test]
+    fn overflowing_arithmetic_is_an_error() {
+        let output = testing::run(
+            "var a = 1_000_000_000_000_000_000_000; \
+             print a * a * a * a * a * a * a * a * a * a * a * a * a * a * a;",
+        );
+
+        assert!(output.contains("RuntimeError: Result of '*' is too large to represent."));
+        assert!(!output.contains("inf"), "{output}");
+    }
+
+    #[test]
+    fn var_declares_each_name_in_order() {
+        assert_eq!(
+            testing::run(
+                "var a = 1, b, c = a + 1; print a; print b; print c; \
+                 { var x = 1, y = x * 2; print y; }"
+            ),
+            "1\nnil\n2\n2\n"
+        );
+    }
+
+    #[test]
+    fn constants_can_be_read_but_not_reassigned() {
+        let output = testing::run("const a = 1; print a; a = 2;");
+
+        assert!(output.starts_with("1\n"), "{output}");
+        assert!(output.contains("RuntimeError: Cannot assign to constant 'a'"));
+        assert!(testing::run("const a = 1; a += 1;").contains("Cannot assign to constant 'a'"));
+    }
+
+    #[test]
+    fn constants_can_not_be_reassigned_from_an_inner_scope() {
+        for source in [
+            "const a = 1; { a = 2; }",
+            "const a = 1; fun f() { a = 3; } f();",
+            "fun f() { const a = 1; fun g() { a = 2; } g(); } f();",
+            "var b; const a = 1; b, a = 1, 2;",
+        ] {
+            let output = testing::run(source);
+
+            assert!(
+                output.contains("Cannot assign to constant 'a'"),
+                "{source}: {output}"
+            );
+        }
+    }
+
+    #[test]
+    fn constants_can_be_shadowed_by_variables() {
+        assert_eq!(
+            testing::run("const a = 1; { var a = 2; a = 3; print a; } print a;"),
+            "3\n1\n"
+        );
+    }
+
+    #[test]
+    fn maps_read_and_write_by_key() {
+        assert_eq!(
+            testing::run(
+                "var m = {\"a\": 1, \"b\": 2}; print m[\"a\"]; \
+                 m[\"a\"] = 3; m[1] = \"one\"; print m[\"a\"]; print m; print {};"
+            ),
+            "1\n3\n{1: one, a: 3, b: 2}\n{}\n"
+        );
+    }
+
+    #[test]
+    fn missing_map_keys_read_as_nil() {
+        assert_eq!(
+            testing::run("var m = {\"a\": 1}; print m[\"zz\"];"),
+            "nil\n"
+        );
+    }
+
+    #[test]
+    fn map_keys_must_be_hashable() {
+        assert!(
+            testing::run("var m = {clock: 1};")
+                .contains("Can not use '<fn clock>' as a key, function values are not hashable.")
+        );
+    }
+
+    #[test]
+    fn lists_are_indexed_by_whole_numbers_in_range() {
+        assert_eq!(
+            testing::run("var l = split(\"x,y\", \",\"); l[1] = \"z\"; print l[1]; print l;"),
+            "z\n[x, z]\n"
+        );
+        assert!(
+            testing::run("var l = split(\"a\", \",\"); print l[1];")
+                .contains("List index 1 is out of range for a list of length 1.")
+        );
+        assert!(
+            testing::run("var l = split(\"a\", \",\"); l[\"x\"] = 1;")
+                .contains("List index must be a whole number but got x.")
+        );
+        assert!(
+            testing::run("print 1[0];").contains("Can only index lists and maps but got number.")
+        );
+    }
+
+    #[test]
+    fn for_in_visits_every_item() {
+        assert_eq!(
+            testing::run("var sum = 0; for (x in range(1, 4)) sum = sum + x; print sum;"),
+            "6\n"
+        );
+    }
+
+    #[test]
+    fn for_in_supports_break_and_continue() {
+        assert_eq!(
+            testing::run(
+                "for (s in split(\"a,b,c,d\", \",\")) { \
+                 if (s == \"b\") continue; if (s == \"d\") break; print s; }"
+            ),
+            "a\nc\n"
+        );
+    }
+
+    #[test]
+    fn for_in_binds_a_fresh_variable_each_iteration() {
+        assert_eq!(
+            testing::run(
+                "var x = \"outer\"; var first; var second; \
+                 for (x in range(0, 2)) { \
+                 fun f() { return x; } if (x == 0) first = f; else second = f; } \
+                 print first(); print second(); print x;"
+            ),
+            "0\n1\nouter\n"
+        );
+    }
+
+    #[test]
+    fn for_in_ignores_a_shadowed_len() {
+        assert_eq!(
+            testing::run("{ fun len(list) { return 0; } for (x in range(0, 2)) print x; }"),
+            "0\n1\n"
+        );
+    }
+
+    #[test]
+    fn nil_coalescing_falls_back_only_on_nil() {
+        assert_eq!(
+            testing::run("print nil ?? 5; print 3 ?? 5; print false ?? 5; print nil ?? nil ?? 7;"),
+            "5\n3\nfalse\n7\n"
+        );
+    }
+
+    #[test]
+    fn nil_coalescing_short_circuits() {
+        assert_eq!(
+            testing::run("fun boom() { print \"boom\"; return 1; } print 2 ?? boom();"),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn block_expressions_yield_their_last_expression() {
+        assert_eq!(
+            testing::run(
+                "fun compute() { return 3; } var x = { var t = compute(); t * 2 }; print x; \
+                 var a = { var t = 1; t; }; print a; var b = { var t = 2; { t + 1 } }; print b; \
+                 print { 1 + 2 };"
+            ),
+            "6\n1\n3\n3\n"
+        );
+    }
+
+    #[test]
+    fn block_expressions_without_a_trailing_expression_yield_nil() {
+        assert_eq!(
+            testing::run("var z = { print \"side\"; }; print z;"),
+            "side\nnil\n"
+        );
+    }
+
+    #[test]
+    fn block_expression_declarations_do_not_leak() {
+        assert_eq!(
+            testing::run("var t = \"outer\"; var c = { var t = \"inner\"; t }; print t; print c;"),
+            "outer\ninner\n"
+        );
+        assert!(
+            testing::run("var x = { var u = 1; u }; print u;").contains("Undefined variable 'u'")
+        );
+    }
+
+    #[test]
+    fn loops_with_large_bodies_run_every_statement() {
+        let body = (0..200)
+            .map(|n| format!("total = total + {n};"))
+            .collect::<String>();
+        let source =
+            format!("var total = 0; for (var i = 0; i < 3; i = i + 1) {{ {body} }} print total;");
+
+        assert_eq!(testing::run(&source), "59700\n");
+    }
+
+    #[test]
+    fn unbraced_branches_and_bodies_keep_control_flow() {
+        assert_eq!(
+            testing::run(
+                "fun f(n) { while (true) if (n > 2) return n; else n = n + 1; } print f(0); \
+                 for (var i = 0; i < 5; i = i + 1) \
+                 if (i == 1) continue; else if (i == 3) break; else print i;"
+            ),
+            "3\n0\n2\n"
+        );
+    }
+
+    #[test]
+    fn conditionals_run_only_the_chosen_branch() {
+        assert_eq!(
+            testing::run(
+                "if (false) print \"no\"; else print \"yes\"; \
+                 if (nil) print \"no\"; \
+                 var j = 0; do j = j + 1; while (j < 3); print j;"
+            ),
+            "yes\n3\n"
+        );
+    }
+
+    #[test]
+    fn nested_calls_evaluate_their_arguments() {
+        assert_eq!(
+            testing::run(
+                "fun f(a, b) { return a * 10 + b; } fun g(x) { return x + 1; } \
+                 print f(g(1), 2); print f(f(1, 2), g(g(0)));"
+            ),
+            "22\n122\n"
+        );
+    }
+
+    #[test]
+    fn arguments_can_assign() {
+        assert_eq!(
+            testing::run("fun id(x) { return x; } var a; print id(a = 2); print a;"),
+            "2\n2\n"
+        );
+    }
+
+    #[test]
+    fn logical_chains_short_circuit_left_to_right() {
+        assert_eq!(
+            testing::run(
+                "fun t(x) { print x; return x; } \
+                 print nil or false or 3; print 1 and 2 and nil; \
+                 print t(1) or t(2) or t(3); print t(nil) and t(2) and t(3);"
+            ),
+            "3\nnil\n1\n1\nnil\nnil\n"
+        );
+    }
+
+    #[test]
+    fn double_negation_round_trips() {
+        assert_eq!(
+            testing::run(
+                "print !!true; print !!nil; print !!!false; \
+                 print - -5; print -(-5); var a = 3; print - - a;"
+            ),
+            "true\nfalse\ntrue\n5\n5\n3\n"
+        );
+    }
+
+    #[test]
+    fn negation_expects_a_number() {
+        assert!(
+            testing::run("print - -\"a\";")
+                .contains("RuntimeError: Operator '-' can only be applied to numbers.")
+        );
+    }
+}
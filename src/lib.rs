@@ -1 +1,24 @@
+#![allow(clippy::result_unit_err)]
 
+pub mod callable;
+pub mod environment;
+pub mod error;
+pub mod expressions;
+pub mod interpreter;
+pub mod natives;
+pub mod optimizer;
+pub mod parser;
+pub mod printer;
+pub mod resolver;
+pub mod runner;
+pub mod scanner;
+pub mod statements;
+pub mod tokens;
+
+pub use error::Error;
+pub use expressions::Literal;
+pub use interpreter::{Clock, Control, Interpreter};
+pub use runner::{Options, Outcome, Repl, run, run_in, run_with};
+
+#[cfg(test)]
+mod testing;
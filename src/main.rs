@@ -1,81 +1,388 @@
-use std::io::{stdin, stdout};
-use std::{fs::read_to_string, io::Write};
-
-use environment::Environment;
-mod environment;
-use error::Error;
-mod callable;
-mod error;
-mod expressions;
-mod interpreter;
-mod parser;
-mod resolver;
-mod scanner;
-mod statements;
-mod tokens;
+use rustyline::{Config, DefaultEditor, error::ReadlineError};
+use std::{
+    env,
+    fs::{read_to_string, write},
+    io::{IsTerminal, Read, stdin},
+    thread,
+};
+
+use lox_interpreter::{
+    Clock, Error, Options, Outcome, Repl, run,
+    runner::{self, MAX_DEPTH, MIN_STACK},
+};
+
+// Each Lox call nests several tree-walking frames, so the interpreter runs on
+// a thread sized so that --max-depth is reached before the real stack runs out.
+// Stacks past MAX_STACK may not be mappable at all, so deeper limits are
+// rejected rather than left to fail when the thread starts.
+const STACK_PER_CALL: usize = 256 * 1024;
+const MAX_STACK: usize = 1024 * 1024 * 1024;
+
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    if let Some(i) = args.iter().position(|arg| arg == flag) {
+        args.remove(i);
+        true
+    } else {
+        false
+    }
+}
+
+fn take_option(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    args.remove(i);
+
+    (i < args.len()).then(|| args.remove(i))
+}
+
+fn is_complete(source: &str) -> bool {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut last = None;
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth -= 1,
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                continue;
+            }
+            _ => (),
+        }
+
+        if !c.is_whitespace() {
+            last = Some(c);
+        }
+    }
+
+    quote.is_none() && depth <= 0 && matches!(last, None | Some(';') | Some('}'))
+}
+
+fn read_input(editor: &mut DefaultEditor) -> Option<String> {
+    let mut source = String::new();
+    let mut prompt = "> ";
+
+    loop {
+        match editor.readline(prompt) {
+            Ok(line) => {
+                let blank = line.trim().is_empty();
+                source.push_str(&line);
+                source.push('\n');
+
+                if blank || source.starts_with(':') || is_complete(&source) {
+                    return Some(source);
+                }
+
+                prompt = "... ";
+            }
+            Err(ReadlineError::Interrupted) => {
+                source.clear();
+                prompt = "> ";
+            }
+            Err(_) => return (!source.is_empty()).then_some(source),
+        }
+    }
+}
+
+fn save_session(path: &str, session: &[String]) {
+    if path.is_empty() {
+        println!("Usage: :save <path>");
+        return;
+    }
+
+    match write(path, session.concat()) {
+        Ok(_) => println!("Saved {} entries to {path}", session.len()),
+        Err(error) => println!("Could not save session to {path}: {error}"),
+    }
+}
+
+// Inputs that don't even scan are left out, so the history only holds
+// attempts worth recalling.
+fn add_history(editor: &mut DefaultEditor, path: &str, source: &str, outcome: Outcome) {
+    if outcome != Outcome::Invalid {
+        _ = editor.add_history_entry(source.trim_end());
+        _ = editor.save_history(path);
+    }
+}
+
+// Both take the environment lookup as a parameter so tests don't have to
+// change the real process environment.
+fn history_path(var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    var("LOXRS_HISTORY").or_else(|| var("HOME").map(|home| format!("{home}/.loxrs_history")))
+}
+
+fn history_size(var: impl Fn(&str) -> Option<String>) -> usize {
+    var("LOXRS_HISTORY_SIZE")
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(1000)
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
+fn usage() -> ! {
+    println!(
+        "Usage: jlox [--trace] [--trace-time] [--time] [--lazy] [--todos] [--step] [--ast] [--tokens] [--feature <name>]... [--max-depth <n>] [--eval <source> | script]"
+    );
+    std::process::exit(1);
+}
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let trace_time = take_flag(&mut args, "--trace-time");
+    let time = take_flag(&mut args, "--time");
+    let trace = take_flag(&mut args, "--trace");
+    let lazy = take_flag(&mut args, "--lazy");
+    let todos = take_flag(&mut args, "--todos");
+    let step = take_flag(&mut args, "--step");
+    let ast = take_flag(&mut args, "--ast");
+    let tokens = take_flag(&mut args, "--tokens");
+    let eval = take_option(&mut args, "--eval");
+    let max_depth = match take_option(&mut args, "--max-depth") {
+        Some(depth) => depth.parse().unwrap_or_else(|_| usage()),
+        None => MAX_DEPTH,
+    };
+    if max_depth > MAX_STACK / STACK_PER_CALL {
+        eprintln!("--max-depth can be at most {}", MAX_STACK / STACK_PER_CALL);
+        std::process::exit(1);
+    }
+    let mut features = Vec::new();
+    while let Some(feature) = take_option(&mut args, "--feature") {
+        features.push(feature);
+    }
     let path = match args.get(1) {
         Some(path) => path.to_owned(),
         None => String::from("REPL"),
     };
 
-    let run = |source: String| {
-        let err = Error::new(&path, Some(source.to_owned()));
+    let options = Options {
+        clock: Clock::system(),
+        trace,
+        trace_time,
+        time,
+        lazy,
+        step,
+        ast,
+        tokens,
+        features,
+        max_depth,
+    };
 
-        let mut scanner = scanner::Scanner::new(&err);
-        let tokens = match scanner.scan_tokens(source) {
-            Ok(tokens) => tokens,
-            Err(_) => return,
-        };
+    let run_repl = || {
+        let err = Error::new(&path, None);
+        let mut repl = Repl::new(&err, &options);
 
-        let statements = match parser::Parser::new(&err).parse(tokens) {
-            Ok(stmts) => stmts,
-            Err(_) => return,
-        };
+        let config = Config::builder()
+            .max_history_size(history_size(env_var))
+            .expect("Invalid history size")
+            .build();
+        let mut editor = DefaultEditor::with_config(config).expect("Could not start line editor");
+        let history = history_path(env_var);
 
-        _ = interpreter::Interpreter::new(&err, Environment::new(None), false)
-            .interpret(statements);
+        if let Some(path) = &history {
+            _ = editor.load_history(path);
+        }
+
+        let mut session = Vec::new();
+
+        while let Some(source) = read_input(&mut editor) {
+            if let Some(path) = source.strip_prefix(":save") {
+                save_session(path.trim(), &session);
+                continue;
+            }
+
+            let outcome = repl.eval(&source);
+
+            if let Some(path) = &history {
+                add_history(&mut editor, path, &source, outcome);
+            }
+
+            if outcome == Outcome::Ran {
+                session.push(source);
+            }
+        }
+
+        repl.finish();
     };
 
-    let run_repl = || {
-        let err = Error::new(&path, None);
+    let cli = || match (eval, args.len()) {
+        (Some(source), 1) => std::process::exit(run("<eval>", source, &options)),
+        (None, 1) if stdin().is_terminal() => run_repl(),
+        (None, 1) => {
+            let mut source = String::new();
+
+            if stdin().read_to_string(&mut source).is_err() {
+                eprintln!("Could not read from stdin");
+                std::process::exit(74);
+            }
+
+            std::process::exit(run("<stdin>", source, &options));
+        }
+        (None, 2) => {
+            let Ok(source) = read_to_string(&path) else {
+                eprintln!("Could not read file: {}", &path);
+                std::process::exit(66);
+            };
 
-        let mut scanner = scanner::Scanner::new(&err);
-        let mut parser = parser::Parser::new(&err);
-        let mut interpreter = interpreter::Interpreter::new(&err, Environment::new(None), true);
-
-        loop {
-            print!("> ");
-            stdout().flush().unwrap();
-            let mut line = String::new();
-            if let Ok(_) = stdin().read_line(&mut line) {
-                let tokens = match scanner.scan_tokens(line) {
-                    Ok(tokens) => tokens,
-                    Err(_) => continue,
-                };
-
-                let statements = match parser.parse(tokens) {
-                    Ok(stmts) => stmts,
-                    Err(_) => continue,
-                };
-
-                _ = interpreter.interpret(statements);
+            if todos {
+                runner::list_todos(&path, source);
             } else {
-                break;
+                std::process::exit(run(&path, source, &options));
             }
         }
+        _ => usage(),
     };
 
-    match args.len() {
-        1 => run_repl(),
-        2 => {
-            run(read_to_string(&path).unwrap_or_else(|_| panic!("Could not read file: {}", &path)));
+    let stack_size = max_depth.saturating_mul(STACK_PER_CALL).max(MIN_STACK);
+
+    thread::scope(|scope| {
+        let cli = thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn_scoped(scope, cli)
+            .unwrap_or_else(|_| {
+                eprintln!("Could not start interpreter thread");
+                std::process::exit(71);
+            });
+
+        if cli.join().is_err() {
+            std::process::exit(101);
         }
-        _ => {
-            println!("Usage: jlox [script]");
-            std::process::exit(1);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_brackets_and_strings_need_more_input() {
+        for source in ["fun f() {\n", "print (1 +\n", "print \"a\n", "var a = 1\n"] {
+            assert!(!is_complete(source), "{source:?}");
+        }
+    }
+
+    #[test]
+    fn closed_statements_are_complete() {
+        for source in [
+            "",
+            "print 1;\n",
+            "fun f() {\n  return 1;\n}\n",
+            "print \"{\";\n",
+        ] {
+            assert!(is_complete(source), "{source:?}");
         }
     }
+
+    #[test]
+    fn comments_do_not_keep_input_open() {
+        assert!(is_complete("print 1; // (\n"));
+        assert!(!is_complete("print 1 // ;\n"));
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let pairs: Vec<(String, String)> = pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        move |name| {
+            pairs
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        }
+    }
+
+    #[test]
+    fn history_lives_in_home_by_default() {
+        assert_eq!(
+            history_path(vars(&[("HOME", "/home/lox")])).as_deref(),
+            Some("/home/lox/.loxrs_history")
+        );
+        assert_eq!(history_path(vars(&[])), None);
+    }
+
+    #[test]
+    fn history_path_can_be_overridden() {
+        let var = vars(&[("HOME", "/home/lox"), ("LOXRS_HISTORY", "/tmp/history")]);
+
+        assert_eq!(history_path(var).as_deref(), Some("/tmp/history"));
+    }
+
+    #[test]
+    fn history_size_falls_back_on_bad_values() {
+        assert_eq!(history_size(vars(&[])), 1000);
+        assert_eq!(history_size(vars(&[("LOXRS_HISTORY_SIZE", "50")])), 50);
+        assert_eq!(history_size(vars(&[("LOXRS_HISTORY_SIZE", "lots")])), 1000);
+    }
+
+    #[test]
+    fn history_keeps_inputs_that_scan_and_reloads_them() {
+        let err = Error::new("<repl>", None);
+        err.set_output(std::io::sink());
+        let mut repl = Repl::new(&err, &Options::default());
+
+        let path = env::temp_dir().join(format!("lox-history-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut editor = DefaultEditor::new().unwrap();
+        for source in ["var a = 1;\n", "var b = @;\n", "print c;\n"] {
+            let outcome = repl.eval(source);
+            add_history(&mut editor, path, source, outcome);
+        }
+
+        let mut reloaded = DefaultEditor::new().unwrap();
+        reloaded.load_history(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let entries: Vec<&String> = reloaded.history().iter().collect();
+        assert_eq!(entries, ["var a = 1;", "print c;"]);
+    }
+
+    #[test]
+    fn saved_sessions_replay_only_the_inputs_that_ran() {
+        let err = Error::new("<repl>", None);
+        err.set_output(std::io::sink());
+        let mut repl = Repl::new(&err, &Options::default());
+
+        let mut session = Vec::new();
+        for source in [
+            "var a = 1;\n",
+            "var c = b;\n",
+            "a = a + 1;\n",
+            "fun f() {\n  return a;\n}\n",
+        ] {
+            if repl.eval(source) == Outcome::Ran {
+                session.push(source.to_owned());
+            }
+        }
+
+        let path = env::temp_dir().join(format!("lox-session-{}.lox", std::process::id()));
+        let path = path.to_str().unwrap();
+        save_session(path, &session);
+        let saved = read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(saved, "var a = 1;\na = a + 1;\nfun f() {\n  return a;\n}\n");
+        assert_eq!(
+            runner::run_with("<saved>", saved, &Options::default(), |interpreter| {
+                interpreter.set_output(std::io::sink())
+            }),
+            0
+        );
+    }
 }
@@ -0,0 +1,1310 @@
+use crate::{
+    callable::{Arity, Callable},
+    environment::Environment,
+    error::ErrorType,
+    expressions::{Key, Literal},
+    interpreter::Interpreter,
+    tokens::Token,
+};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+type Native = fn(&mut Interpreter, &Token, Vec<Literal>) -> Result<Literal, ()>;
+
+// range() builds its whole list up front, so anything longer than this is
+// reported instead of exhausting memory.
+const MAX_RANGE: f64 = 10_000_000.0;
+
+pub fn define(environment: &mut Environment) {
+    native(environment, "clock", Arity::Exact(0), clock);
+    native(environment, "len", Arity::Exact(1), len);
+    native(environment, "byte_len", Arity::Exact(1), byte_len);
+    native(environment, "charAt", Arity::Exact(2), char_at);
+    native(environment, "substr", Arity::Exact(3), substr);
+    native(environment, "split", Arity::Exact(2), split);
+    native(environment, "join", Arity::Exact(2), join);
+    native(environment, "input", Arity::Exact(0), input);
+    native(environment, "lines", Arity::Exact(1), lines);
+    native(environment, "line_count", Arity::Exact(1), line_count);
+    native(environment, "expect_error", Arity::Exact(1), expect_error);
+    native(environment, "range", Arity::Between(2, 3), range);
+    native(environment, "enumerate", Arity::Exact(1), enumerate);
+    native(environment, "source", Arity::Exact(1), source);
+    native(environment, "curry", Arity::Exact(1), curry);
+    native(environment, "group", Arity::Exact(1), group);
+    native(environment, "str", Arity::Exact(1), str);
+    native(environment, "pretty", Arity::Exact(1), pretty);
+    native(environment, "format_map", Arity::Exact(2), format_map);
+    native(environment, "num", Arity::Exact(1), num);
+    native(environment, "floor", Arity::Exact(1), floor);
+    native(environment, "ceil", Arity::Exact(1), ceil);
+    native(environment, "round", Arity::Exact(1), round);
+    native(environment, "abs", Arity::Exact(1), abs);
+    native(environment, "sqrt", Arity::Exact(1), sqrt);
+    native(environment, "idiv", Arity::Exact(2), idiv);
+    native(environment, "imod", Arity::Exact(2), imod);
+    native(environment, "pow", Arity::Exact(2), pow);
+    native(environment, "min", Arity::AtLeast(2), min);
+    native(environment, "max", Arity::AtLeast(2), max);
+    native(environment, "set", Arity::Between(0, 1), set);
+    native(environment, "add", Arity::Exact(2), add);
+    native(environment, "remove", Arity::Exact(2), remove);
+    native(environment, "contains", Arity::Exact(2), contains);
+    native(environment, "union", Arity::Exact(2), union);
+    native(environment, "intersection", Arity::Exact(2), intersection);
+    native(environment, "type", Arity::Exact(1), type_of);
+    native(environment, "feature", Arity::Exact(1), feature);
+}
+
+// Desugared for-in loops call len through this value, so user bindings with
+// the same name can't change what they do.
+pub fn len_builtin() -> Literal {
+    callable("len", Arity::Exact(1), len)
+}
+
+fn native(environment: &mut Environment, name: &str, arity: Arity, func: Native) {
+    environment.builtins.insert(name.to_owned());
+    environment
+        .values
+        .insert(name.to_owned(), callable(name, arity, func));
+}
+
+fn callable(name: &str, arity: Arity, func: Native) -> Literal {
+    Literal::Callable(Callable::native(
+        name,
+        arity,
+        Rc::new(move |interpreter, paren, _, args| {
+            let args = args
+                .into_iter()
+                .map(|arg| interpreter.force(arg, paren.location()))
+                .collect::<Result<_, _>>()?;
+
+            func(interpreter, paren, args)
+        }),
+    ))
+}
+
+fn error<T>(interpreter: &Interpreter, paren: &Token, message: &str) -> Result<T, ()> {
+    interpreter
+        .error
+        .report(paren.location(), ErrorType::RuntimeError, message);
+    Err(())
+}
+
+fn clock(_: &mut Interpreter, _: &Token, _: Vec<Literal>) -> Result<Literal, ()> {
+    Ok(Literal::Number(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64(),
+    ))
+}
+
+fn len(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    match &args[0] {
+        Literal::String(s) => Ok(Literal::Number(s.chars().count() as f64)),
+        Literal::List(list) => Ok(Literal::Number(list.borrow().len() as f64)),
+        _ => error(interpreter, paren, "len() expects a string or a list."),
+    }
+}
+
+fn byte_len(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    args: Vec<Literal>,
+) -> Result<Literal, ()> {
+    match &args[0] {
+        Literal::String(s) => Ok(Literal::Number(s.len() as f64)),
+        _ => error(interpreter, paren, "byte_len() expects a string."),
+    }
+}
+
+// Strings are indexed by character rather than by byte, so multi-byte
+// characters count as one position.
+fn index(
+    interpreter: &Interpreter,
+    paren: &Token,
+    value: &Literal,
+    name: &str,
+) -> Result<usize, ()> {
+    match value {
+        Literal::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as usize),
+        value => error(
+            interpreter,
+            paren,
+            &format!("{name}() expects a whole number index but got {value}."),
+        ),
+    }
+}
+
+fn char_at(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    args: Vec<Literal>,
+) -> Result<Literal, ()> {
+    let Literal::String(s) = &args[0] else {
+        return error(interpreter, paren, "charAt() expects a string.");
+    };
+    let i = index(interpreter, paren, &args[1], "charAt")?;
+
+    match s.chars().nth(i) {
+        Some(c) => Ok(Literal::String(c.to_string())),
+        None => error(
+            interpreter,
+            paren,
+            &format!(
+                "charAt() index {i} is out of range for a string of length {}.",
+                s.chars().count()
+            ),
+        ),
+    }
+}
+
+fn substr(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let Literal::String(s) = &args[0] else {
+        return error(interpreter, paren, "substr() expects a string.");
+    };
+    let start = index(interpreter, paren, &args[1], "substr")?;
+    let end = index(interpreter, paren, &args[2], "substr")?;
+    let length = s.chars().count();
+
+    if start > end || end > length {
+        return error(
+            interpreter,
+            paren,
+            &format!(
+                "substr() range {start} to {end} is out of range for a string of length {length}."
+            ),
+        );
+    }
+
+    Ok(Literal::String(
+        s.chars().skip(start).take(end - start).collect(),
+    ))
+}
+
+// An empty separator splits a string into its characters.
+fn split(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let (Literal::String(s), Literal::String(separator)) = (&args[0], &args[1]) else {
+        return error(
+            interpreter,
+            paren,
+            "split() expects a string and a string separator.",
+        );
+    };
+
+    let parts: Vec<Literal> = if separator.is_empty() {
+        s.chars().map(|c| Literal::String(c.to_string())).collect()
+    } else {
+        s.split(separator.as_str())
+            .map(|part| Literal::String(part.to_owned()))
+            .collect()
+    };
+
+    Ok(Literal::List(Rc::new(RefCell::new(parts))))
+}
+
+fn join(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let (Literal::List(list), Literal::String(separator)) = (&args[0], &args[1]) else {
+        return error(
+            interpreter,
+            paren,
+            "join() expects a list and a string separator, list values are converted as by str().",
+        );
+    };
+
+    let parts: Vec<String> = list
+        .borrow()
+        .iter()
+        .map(|value| value.to_string())
+        .collect();
+
+    Ok(Literal::String(parts.join(separator)))
+}
+
+fn input(interpreter: &mut Interpreter, paren: &Token, _: Vec<Literal>) -> Result<Literal, ()> {
+    let mut line = String::new();
+
+    match interpreter.read_line(&mut line) {
+        Ok(0) => Ok(Literal::Nil),
+        Ok(_) => {
+            if line.ends_with('\n') {
+                line.pop();
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+            }
+
+            Ok(Literal::String(line))
+        }
+        Err(_) => error(interpreter, paren, "Could not read input."),
+    }
+}
+
+fn lines(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    match &args[0] {
+        Literal::String(s) => Ok(Literal::List(Rc::new(RefCell::new(
+            s.lines().map(|l| Literal::String(l.to_owned())).collect(),
+        )))),
+        _ => error(interpreter, paren, "lines() expects a string."),
+    }
+}
+
+fn line_count(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    args: Vec<Literal>,
+) -> Result<Literal, ()> {
+    match &args[0] {
+        Literal::String(s) => Ok(Literal::Number(s.lines().count() as f64)),
+        _ => error(interpreter, paren, "line_count() expects a string."),
+    }
+}
+
+fn expect_error(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    args: Vec<Literal>,
+) -> Result<Literal, ()> {
+    match &args[0] {
+        Literal::Callable(callable) if callable.arity().accepts(0) => {
+            let error = interpreter.error;
+            let (res, messages) = error.capture(|| callable.call(interpreter, paren, Vec::new()));
+
+            match res {
+                Ok(..) => Ok(Literal::Nil),
+                Err(..) => Ok(Literal::String(
+                    messages.into_iter().next().unwrap_or_default(),
+                )),
+            }
+        }
+        _ => error(
+            interpreter,
+            paren,
+            "expect_error() expects a function with no parameters.",
+        ),
+    }
+}
+
+fn range(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let mut numbers = Vec::new();
+    for arg in &args {
+        match arg {
+            Literal::Number(n) => numbers.push(*n),
+            _ => return error(interpreter, paren, "range() expects numbers."),
+        }
+    }
+
+    let (start, end) = (numbers[0], numbers[1]);
+    let step = numbers.get(2).copied().unwrap_or(1.0);
+
+    if step == 0.0 {
+        return error(interpreter, paren, "range() step can not be 0.");
+    }
+
+    // Each value is computed from its index rather than by adding the step
+    // over and over, so fractional steps don't drift past the end.
+    let count = ((end - start) / step).ceil().max(0.0);
+
+    if !count.is_finite() || count > MAX_RANGE {
+        return error(
+            interpreter,
+            paren,
+            &format!("range() can not produce more than {MAX_RANGE} values."),
+        );
+    }
+
+    let values = (0..count as usize)
+        .map(|i| Literal::Number(start + i as f64 * step))
+        .collect();
+
+    Ok(Literal::List(Rc::new(RefCell::new(values))))
+}
+
+fn enumerate(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    args: Vec<Literal>,
+) -> Result<Literal, ()> {
+    match &args[0] {
+        Literal::List(list) => Ok(Literal::List(Rc::new(RefCell::new(
+            list.borrow()
+                .iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    Literal::List(Rc::new(RefCell::new(vec![
+                        Literal::Number(i as f64),
+                        value.clone(),
+                    ])))
+                })
+                .collect(),
+        )))),
+        _ => error(interpreter, paren, "enumerate() expects a list."),
+    }
+}
+
+fn source(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    match &args[0] {
+        Literal::Callable(callable) => Ok(callable
+            .source()
+            .map_or(Literal::Nil, |source| Literal::String(source.to_owned()))),
+        _ => error(interpreter, paren, "source() expects a function."),
+    }
+}
+
+fn curry(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    match &args[0] {
+        Literal::Callable(callable) => match callable.arity() {
+            Arity::Exact(0 | 1) => Ok(args[0].clone()),
+            Arity::Exact(arity) => Ok(curried(callable.clone(), arity, Vec::new())),
+            _ => error(
+                interpreter,
+                paren,
+                "curry() expects a function with a fixed number of parameters.",
+            ),
+        },
+        _ => error(interpreter, paren, "curry() expects a function."),
+    }
+}
+
+// Each step takes one argument and hands back the next step until the
+// original function has all of its arguments.
+fn curried(callable: Callable, arity: usize, applied: Vec<Literal>) -> Literal {
+    let name = callable.name().unwrap_or("curry").to_owned();
+
+    Literal::Callable(Callable::native(
+        &name,
+        Arity::Exact(1),
+        Rc::new(move |interpreter, paren, _, args| {
+            let mut applied = applied.clone();
+            applied.extend(args);
+
+            if applied.len() == arity {
+                callable.call(interpreter, paren, applied)
+            } else {
+                Ok(curried(callable.clone(), arity, applied))
+            }
+        }),
+    ))
+}
+
+fn group(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let Literal::Number(n) = args[0] else {
+        return error(interpreter, paren, "group() expects a number.");
+    };
+
+    let digits = n.abs().to_string();
+    let (whole, fraction) = match digits.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (&digits[..], None),
+    };
+
+    let mut grouped = String::new();
+
+    if n < 0.0 {
+        grouped.push('-');
+    }
+
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+
+    if let Some(fraction) = fraction {
+        grouped.push('.');
+        grouped.push_str(fraction);
+    }
+
+    Ok(Literal::String(grouped))
+}
+
+fn str(_: &mut Interpreter, _: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    Ok(Literal::String(args[0].to_string()))
+}
+
+fn pretty(_: &mut Interpreter, _: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    Ok(Literal::String(args[0].pretty()))
+}
+
+// Replaces each {key} in the template with the map's value for that string key,
+// written as by str(). A key the map doesn't have is an error rather than being
+// left in place, and {{ and }} stand for literal braces.
+fn format_map(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    args: Vec<Literal>,
+) -> Result<Literal, ()> {
+    let (Literal::String(template), Literal::Map(map)) = (&args[0], &args[1]) else {
+        return error(
+            interpreter,
+            paren,
+            "format_map() expects a string template and a map.",
+        );
+    };
+
+    let map = map.borrow();
+    let mut formatted = String::new();
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                formatted.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                formatted.push('}');
+            }
+            '{' => {
+                let Some((name, rest)) = chars.as_str().split_once('}') else {
+                    return error(interpreter, paren, "Unclosed '{' in format_map() template.");
+                };
+
+                let Some(value) = map.get(&Key::String(name.to_owned())) else {
+                    return error(
+                        interpreter,
+                        paren,
+                        &format!("No key '{name}' in the map given to format_map()."),
+                    );
+                };
+
+                formatted.push_str(&value.to_string());
+                chars = rest.chars();
+            }
+            '}' => {
+                return error(
+                    interpreter,
+                    paren,
+                    "Unmatched '}' in format_map() template, use '}}' for a literal brace.",
+                );
+            }
+            c => formatted.push(c),
+        }
+    }
+
+    Ok(Literal::String(formatted))
+}
+
+fn num(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    match &args[0] {
+        Literal::String(s) => Ok(s
+            .trim()
+            .parse()
+            .ok()
+            .filter(|n: &f64| n.is_finite())
+            .map_or(Literal::Nil, Literal::Number)),
+        _ => error(interpreter, paren, "num() expects a string."),
+    }
+}
+
+fn math(
+    interpreter: &Interpreter,
+    paren: &Token,
+    value: &Literal,
+    name: &str,
+    op: fn(f64) -> f64,
+) -> Result<Literal, ()> {
+    match value {
+        Literal::Number(n) => match op(*n) {
+            result if result.is_finite() => Ok(Literal::Number(result)),
+            _ => error(
+                interpreter,
+                paren,
+                &format!("{name}() is not defined for {value}."),
+            ),
+        },
+        _ => error(interpreter, paren, &format!("{name}() expects a number.")),
+    }
+}
+
+fn floor(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    math(interpreter, paren, &args[0], "floor", f64::floor)
+}
+
+fn ceil(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    math(interpreter, paren, &args[0], "ceil", f64::ceil)
+}
+
+fn round(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    math(interpreter, paren, &args[0], "round", f64::round)
+}
+
+fn abs(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    math(interpreter, paren, &args[0], "abs", f64::abs)
+}
+
+fn sqrt(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    math(interpreter, paren, &args[0], "sqrt", f64::sqrt)
+}
+
+fn numbers(
+    interpreter: &Interpreter,
+    paren: &Token,
+    args: &[Literal],
+    name: &str,
+) -> Result<Vec<f64>, ()> {
+    args.iter()
+        .map(|arg| match arg {
+            Literal::Number(n) => Ok(*n),
+            _ => error(interpreter, paren, &format!("{name}() expects numbers.")),
+        })
+        .collect()
+}
+
+fn integers(
+    interpreter: &Interpreter,
+    paren: &Token,
+    args: &[Literal],
+    name: &str,
+) -> Result<(f64, f64), ()> {
+    let numbers = numbers(interpreter, paren, args, name)?;
+    let (left, right) = (numbers[0].trunc(), numbers[1].trunc());
+
+    if right == 0.0 {
+        return error(
+            interpreter,
+            paren,
+            &format!("{name}() can not divide by 0."),
+        );
+    }
+
+    Ok((left, right))
+}
+
+fn idiv(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let (left, right) = integers(interpreter, paren, &args, "idiv")?;
+    Ok(Literal::Number((left / right).trunc()))
+}
+
+fn imod(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let (left, right) = integers(interpreter, paren, &args, "imod")?;
+    Ok(Literal::Number(left % right))
+}
+
+// 0 to the power of 0 is 1, while a negative base with a fractional exponent
+// and results too large to represent are errors rather than NaN or infinity.
+fn pow(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let numbers = numbers(interpreter, paren, &args, "pow")?;
+    let (base, exponent) = (numbers[0], numbers[1]);
+
+    if base < 0.0 && exponent.fract() != 0.0 {
+        return error(
+            interpreter,
+            paren,
+            "pow() can not raise a negative number to a fractional power.",
+        );
+    }
+
+    if base == 0.0 && exponent < 0.0 {
+        return error(
+            interpreter,
+            paren,
+            "pow() can not raise 0 to a negative power.",
+        );
+    }
+
+    match base.powf(exponent) {
+        result if result.is_finite() => Ok(Literal::Number(result)),
+        _ => error(
+            interpreter,
+            paren,
+            "pow() result is too large to represent.",
+        ),
+    }
+}
+
+fn min(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let numbers = numbers(interpreter, paren, &args, "min")?;
+    Ok(Literal::Number(
+        numbers.into_iter().fold(f64::INFINITY, f64::min),
+    ))
+}
+
+fn max(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let numbers = numbers(interpreter, paren, &args, "max")?;
+    Ok(Literal::Number(
+        numbers.into_iter().fold(f64::NEG_INFINITY, f64::max),
+    ))
+}
+
+fn key(interpreter: &Interpreter, paren: &Token, value: &Literal) -> Result<Key, ()> {
+    Key::from_literal(value).map_err(|message| {
+        interpreter
+            .error
+            .report(paren.location(), ErrorType::RuntimeError, &message);
+    })
+}
+
+fn set(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let mut keys = HashSet::new();
+
+    match args.first() {
+        Some(Literal::List(list)) => {
+            for value in list.borrow().iter() {
+                keys.insert(key(interpreter, paren, value)?);
+            }
+        }
+        Some(_) => return error(interpreter, paren, "set() expects a list."),
+        None => (),
+    }
+
+    Ok(Literal::Set(Rc::new(RefCell::new(keys))))
+}
+
+fn add(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let Literal::Set(set) = &args[0] else {
+        return error(interpreter, paren, "add() expects a set.");
+    };
+
+    let key = key(interpreter, paren, &args[1])?;
+    set.borrow_mut().insert(key);
+
+    Ok(Literal::Nil)
+}
+
+fn remove(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let Literal::Set(set) = &args[0] else {
+        return error(interpreter, paren, "remove() expects a set.");
+    };
+
+    let removed = Key::from_literal(&args[1]).is_ok_and(|key| set.borrow_mut().remove(&key));
+
+    Ok(Literal::Boolean(removed))
+}
+
+fn contains(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    args: Vec<Literal>,
+) -> Result<Literal, ()> {
+    let Literal::Set(set) = &args[0] else {
+        return error(interpreter, paren, "contains() expects a set.");
+    };
+
+    let found = Key::from_literal(&args[1]).is_ok_and(|key| set.borrow().contains(&key));
+
+    Ok(Literal::Boolean(found))
+}
+
+fn union(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    let (Literal::Set(left), Literal::Set(right)) = (&args[0], &args[1]) else {
+        return error(interpreter, paren, "union() expects two sets.");
+    };
+
+    let keys = left.borrow().union(&right.borrow()).cloned().collect();
+
+    Ok(Literal::Set(Rc::new(RefCell::new(keys))))
+}
+
+fn intersection(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    args: Vec<Literal>,
+) -> Result<Literal, ()> {
+    let (Literal::Set(left), Literal::Set(right)) = (&args[0], &args[1]) else {
+        return error(interpreter, paren, "intersection() expects two sets.");
+    };
+
+    let keys = left
+        .borrow()
+        .intersection(&right.borrow())
+        .cloned()
+        .collect();
+
+    Ok(Literal::Set(Rc::new(RefCell::new(keys))))
+}
+
+fn type_of(_: &mut Interpreter, _: &Token, args: Vec<Literal>) -> Result<Literal, ()> {
+    Ok(Literal::String(args[0].type_name().to_owned()))
+}
+
+fn feature(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    args: Vec<Literal>,
+) -> Result<Literal, ()> {
+    match &args[0] {
+        Literal::String(name) => Ok(Literal::Boolean(interpreter.has_feature(name))),
+        _ => error(interpreter, paren, "feature() expects a string."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{runner::Options, testing};
+    use std::io::Cursor;
+
+    fn run_with_input(source: &str, input: &str) -> String {
+        let input = Cursor::new(input.to_owned());
+        testing::run_with(source, &Options::default(), |interpreter| {
+            interpreter.set_input(input)
+        })
+        .1
+    }
+
+    #[test]
+    fn format_map_substitutes_keys() {
+        let output = testing::run(
+            "var m = {\"hi\": \"Hi\", \"name\": \"Lox\", \"n\": 2};
+            print format_map(\"{hi}, {name}! {n}\", m);",
+        );
+
+        assert_eq!(output, "Hi, Lox! 2\n");
+    }
+
+    #[test]
+    fn format_map_reports_a_missing_key() {
+        let output = testing::run("print format_map(\"{a} {b}\", {\"a\": 1});");
+
+        assert!(
+            output.contains("No key 'b' in the map given to format_map()."),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn format_map_unescapes_doubled_braces() {
+        let output = testing::run("print format_map(\"{{{a}}} }}{{\", {\"a\": 1});");
+
+        assert_eq!(output, "{1} }{\n");
+    }
+
+    #[test]
+    fn format_map_rejects_stray_braces() {
+        for template in ["{a", "a}"] {
+            let output = testing::run(&format!("format_map(\"{template}\", {{}});"));
+
+            assert!(output.contains("in format_map() template"), "{output}");
+        }
+    }
+
+    #[test]
+    fn input_reads_one_line_at_a_time() {
+        let output = run_with_input("print input(); print input();", "first\r\nsecond\nthird\n");
+
+        assert_eq!(output, "first\nsecond\n");
+    }
+
+    #[test]
+    fn input_returns_nil_at_the_end_of_input() {
+        let output = run_with_input("print input(); print input();", "last");
+
+        assert_eq!(output, "last\nnil\n");
+    }
+
+    #[test]
+    fn lines_splits_on_any_line_ending() {
+        let output = testing::run(
+            "print lines(\"a\nb\n\nc\"); print lines(\"x\r\ny\"); print len(lines(\"\"));",
+        );
+
+        assert_eq!(output, "[a, b, , c]\n[x, y]\n0\n");
+    }
+
+    #[test]
+    fn line_count_ignores_a_trailing_newline() {
+        let output = testing::run("print line_count(\"a\nb\n\"); print line_count(\"\");");
+
+        assert_eq!(output, "2\n0\n");
+    }
+
+    #[test]
+    fn line_natives_expect_strings() {
+        assert!(testing::run("lines(1);").contains("lines() expects a string."));
+        assert!(testing::run("line_count(1);").contains("line_count() expects a string."));
+    }
+
+    #[test]
+    fn expect_error_returns_the_first_error_message() {
+        let (status, output) = testing::run_with(
+            "print expect_error(fun () => nil + 1); print \"after\";",
+            &Options::default(),
+            |_| (),
+        );
+
+        assert_eq!(status, 0);
+        assert_eq!(output, "Cannot apply '+' to nil.\nafter\n");
+    }
+
+    #[test]
+    fn expect_error_returns_nil_when_nothing_fails() {
+        assert_eq!(testing::run("print expect_error(fun () => 1);"), "nil\n");
+    }
+
+    #[test]
+    fn expect_error_wants_a_function_without_parameters() {
+        let output = testing::run("expect_error(fun (x) => x);");
+
+        assert!(output.contains("expect_error() expects a function with no parameters."));
+    }
+
+    #[test]
+    fn len_counts_characters_and_list_items() {
+        let output = testing::run(
+            "print len(\"héllo\"); print len(\"\"); print len(split(\"a,b\", \",\"));",
+        );
+
+        assert_eq!(output, "5\n0\n2\n");
+    }
+
+    #[test]
+    fn len_rejects_other_values() {
+        assert!(testing::run("len(1);").contains("len() expects a string or a list."));
+    }
+
+    #[test]
+    fn range_counts_up_or_down_by_its_step() {
+        let output =
+            testing::run("print range(0, 3); print range(5, 0, -2); print range(0, 1, 0.25);");
+
+        assert_eq!(output, "[0, 1, 2]\n[5, 3, 1]\n[0, 0.25, 0.5, 0.75]\n");
+    }
+
+    #[test]
+    fn range_is_empty_when_the_step_points_away_from_the_end() {
+        assert_eq!(
+            testing::run("print range(3, 0); print range(0, 3, -1);"),
+            "[]\n[]\n"
+        );
+    }
+
+    #[test]
+    fn range_does_not_drift_on_fractional_steps() {
+        assert_eq!(
+            testing::run("print len(range(0, 1, 0.1)); print range(0, 0.3, 0.1);"),
+            "10\n[0, 0.1, 0.2]\n"
+        );
+    }
+
+    #[test]
+    fn range_rejects_ranges_too_long_to_build() {
+        assert!(
+            testing::run("range(0, 300000000);")
+                .contains("range() can not produce more than 10000000 values.")
+        );
+        assert!(testing::run("range(0, 1, 1 / 1000000000);").contains("can not produce more than"));
+    }
+
+    #[test]
+    fn range_rejects_a_zero_step_and_non_numbers() {
+        assert!(testing::run("range(0, 3, 0);").contains("range() step can not be 0."));
+        assert!(testing::run("range(\"a\", 2);").contains("range() expects numbers."));
+    }
+
+    #[test]
+    fn enumerate_pairs_each_item_with_its_index() {
+        let output =
+            testing::run("print enumerate(split(\"a,b\", \",\")); print enumerate(range(0, 0));");
+
+        assert_eq!(output, "[[0, a], [1, b]]\n[]\n");
+    }
+
+    #[test]
+    fn enumerate_copies_the_items() {
+        let output = testing::run(
+            "var l = split(\"a\", \",\"); var pairs = enumerate(l); l[0] = \"b\"; print pairs;",
+        );
+
+        assert_eq!(output, "[[0, a]]\n");
+    }
+
+    #[test]
+    fn enumerate_expects_a_list() {
+        assert!(testing::run("enumerate(\"ab\");").contains("enumerate() expects a list."));
+    }
+
+    #[test]
+    fn source_returns_a_function_body_as_written() {
+        let output = testing::run(
+            "fun f(a) {\n  return a;\n}\n\
+            var g = fun (a, b) { return b; };\n\
+            print source(f); print source(g);",
+        );
+
+        assert_eq!(output, "{\n  return a;\n}\n{ return b; }\n");
+    }
+
+    #[test]
+    fn source_of_a_native_is_nil() {
+        assert_eq!(testing::run("print source(clock);"), "nil\n");
+    }
+
+    #[test]
+    fn source_expects_a_function() {
+        assert!(testing::run("source(1);").contains("source() expects a function."));
+    }
+
+    #[test]
+    fn group_separates_thousands_in_the_whole_part() {
+        let output = testing::run(
+            "print group(1234567); print group(-1234.5); print group(999); print group(0);
+            print group(1000000.25);",
+        );
+
+        assert_eq!(output, "1,234,567\n-1,234.5\n999\n0\n1,000,000.25\n");
+    }
+
+    #[test]
+    fn group_expects_a_number() {
+        assert!(testing::run("group(\"x\");").contains("group() expects a number."));
+    }
+
+    #[test]
+    fn str_formats_any_value() {
+        assert_eq!(
+            testing::run(
+                "print str(42) + \"!\"; print str(12.5); print str(nil); print str(true);"
+            ),
+            "42!\n12.5\nnil\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn num_parses_strings() {
+        assert_eq!(testing::run("print num(\"3.14\");"), "3.14\n");
+        assert_eq!(testing::run("print num(\" 4 \");"), "4\n");
+    }
+
+    #[test]
+    fn num_returns_nil_when_parsing_fails() {
+        assert_eq!(testing::run("print num(\"abc\");"), "nil\n");
+    }
+
+    #[test]
+    fn num_expects_a_string() {
+        assert!(testing::run("print num(1);").contains("RuntimeError: num() expects a string."));
+    }
+
+    #[test]
+    fn set_drops_duplicates() {
+        assert_eq!(
+            testing::run("print set(split(\"b,a,b\", \",\"));"),
+            "{a, b}\n"
+        );
+    }
+
+    #[test]
+    fn set_membership_follows_add_and_remove() {
+        assert_eq!(
+            testing::run(
+                "var s = set(split(\"a,b\", \",\")); \
+                 print contains(s, \"a\"); print contains(s, \"c\"); \
+                 add(s, \"c\"); print remove(s, \"a\"); print remove(s, \"a\"); print s;"
+            ),
+            "true\nfalse\ntrue\nfalse\n{b, c}\n"
+        );
+    }
+
+    #[test]
+    fn union_and_intersection_combine_sets() {
+        assert_eq!(
+            testing::run(
+                "print union(set(range(0, 2)), set(range(1, 3))); \
+                 print intersection(set(range(0, 3)), set(range(1, 5)));"
+            ),
+            "{0, 1, 2}\n{1, 2}\n"
+        );
+    }
+
+    #[test]
+    fn sets_reject_unhashable_values() {
+        assert!(
+            testing::run("add(set(range(0, 1)), clock);")
+                .contains("Can not use '<fn clock>' as a key, function values are not hashable.")
+        );
+        assert!(
+            testing::run("set(enumerate(range(0, 1)));").contains("list values are not hashable.")
+        );
+        assert!(testing::run("set(1);").contains("set() expects a list."));
+    }
+
+    #[test]
+    fn type_names_every_kind_of_value() {
+        assert_eq!(
+            testing::run(
+                "fun f() {} \
+                 print type(1); print type(\"s\"); print type(true); print type(nil); \
+                 print type(f); print type(clock); print type(fun () {}); \
+                 print type(range(0, 1)); print type(set(range(0, 1))); print type({\"a\": 1});"
+            ),
+            "number\nstring\nboolean\nnil\nfunction\nfunction\nfunction\nlist\nset\nmap\n"
+        );
+    }
+
+    #[test]
+    fn type_forces_lazy_values() {
+        let options = Options {
+            lazy: true,
+            ..Options::default()
+        };
+
+        assert_eq!(
+            testing::run_with("var x = 1; print type(x);", &options, |_| ()).1,
+            "number\n"
+        );
+    }
+
+    #[test]
+    fn feature_reports_the_flags_the_host_enabled() {
+        let source = "if (feature(\"fast\")) print \"fast\"; else print \"slow\"; \
+                      if (feature(\"color\")) print \"color\"; else print \"plain\";";
+        let (_, output) = testing::run_with(source, &Options::default(), |interpreter| {
+            interpreter.set_feature("fast", true);
+            interpreter.set_feature("color", true);
+            interpreter.set_feature("color", false);
+        });
+
+        assert_eq!(output, "fast\nplain\n");
+    }
+
+    #[test]
+    fn feature_expects_a_string() {
+        assert!(testing::run("feature(1);").contains("feature() expects a string."));
+    }
+
+    #[test]
+    fn byte_len_counts_utf8_bytes() {
+        assert_eq!(
+            testing::run("print len(\"abc\"); print byte_len(\"abc\");"),
+            "3\n3\n"
+        );
+        assert_eq!(
+            testing::run("print len(\"héllo→\"); print byte_len(\"héllo→\");"),
+            "6\n9\n"
+        );
+    }
+
+    #[test]
+    fn byte_len_expects_a_string() {
+        assert!(testing::run("byte_len(1);").contains("byte_len() expects a string."));
+    }
+
+    #[test]
+    fn math_natives_round_and_measure_numbers() {
+        assert_eq!(
+            testing::run(
+                "print floor(-2.5); print floor(2.7); print ceil(-2.5); print ceil(2.1); \
+                 print round(-2.5); print round(2.4); print abs(-3); print sqrt(16);"
+            ),
+            "-3\n2\n-2\n3\n-3\n2\n3\n4\n"
+        );
+    }
+
+    #[test]
+    fn math_natives_expect_numbers() {
+        assert!(testing::run("floor(\"a\");").contains("floor() expects a number."));
+        assert!(testing::run("abs(nil);").contains("abs() expects a number."));
+    }
+
+    #[test]
+    fn sqrt_rejects_negative_numbers() {
+        assert!(testing::run("sqrt(-1);").contains("sqrt() is not defined for -1."));
+    }
+
+    #[test]
+    fn min_and_max_take_any_number_of_arguments() {
+        assert_eq!(
+            testing::run(
+                "print min(3, 1); print max(3, 1); print max(1, -2, 8, 4); print min(2, 7, -1, 5);"
+            ),
+            "1\n3\n8\n-1\n"
+        );
+    }
+
+    #[test]
+    fn min_and_max_need_two_numbers() {
+        assert!(
+            testing::run("min();")
+                .contains("RuntimeError: Expected at least 2 arguments to 'min' but got 0.")
+        );
+        assert!(
+            testing::run("max(1);").contains("Expected at least 2 arguments to 'max' but got 1.")
+        );
+        assert!(testing::run("max(1, \"a\");").contains("max() expects numbers."));
+    }
+
+    #[test]
+    fn idiv_truncates_where_slash_does_not() {
+        assert_eq!(
+            testing::run("print 7 / 2; print idiv(7, 2); print idiv(-7, 2); print idiv(7.9, 2);"),
+            "3.5\n3\n-3\n3\n"
+        );
+    }
+
+    #[test]
+    fn imod_keeps_the_sign_of_the_dividend() {
+        assert_eq!(
+            testing::run("print imod(7, 3); print imod(-7, 3);"),
+            "1\n-1\n"
+        );
+    }
+
+    #[test]
+    fn integer_natives_reject_zero_and_non_numbers() {
+        assert!(testing::run("idiv(1, 0);").contains("idiv() can not divide by 0."));
+        assert!(testing::run("imod(1, 0);").contains("imod() can not divide by 0."));
+        assert!(testing::run("idiv(\"a\", 1);").contains("idiv() expects numbers."));
+    }
+
+    #[test]
+    fn curry_applies_one_argument_at_a_time() {
+        assert_eq!(
+            testing::run(
+                "fun add3(a, b, c) { return a + b * 10 + c * 100; } \
+                 var c = curry(add3); print c(1)(2)(3); \
+                 var p = c(1); print p(5)(6); print p(7)(8);"
+            ),
+            "321\n651\n871\n"
+        );
+    }
+
+    #[test]
+    fn curry_passes_short_functions_through() {
+        assert_eq!(
+            testing::run(
+                "fun one(x) { return x; } fun zero() { return 9; } \
+                 print curry(one)(4); print curry(zero)();"
+            ),
+            "4\n9\n"
+        );
+    }
+
+    #[test]
+    fn curried_steps_take_exactly_one_argument() {
+        assert!(
+            testing::run("fun add(a, b) { return a + b; } curry(add)(1, 2);")
+                .contains("Expected 1 arguments to 'add' but got 2.")
+        );
+    }
+
+    #[test]
+    fn curry_expects_a_fixed_arity_function() {
+        assert!(testing::run("curry(1);").contains("curry() expects a function."));
+        assert!(
+            testing::run("curry(min);")
+                .contains("curry() expects a function with a fixed number of parameters.")
+        );
+    }
+
+    #[test]
+    fn num_refuses_nan_and_infinity() {
+        assert_eq!(
+            testing::run("print num(\"NaN\"); print num(\"inf\"); print num(\"-infinity\");"),
+            "nil\nnil\nnil\n"
+        );
+    }
+
+    #[test]
+    fn pow_edge_cases() {
+        for (args, expected) in [
+            ("0, 0", "1\n"),
+            ("2, 10", "1024\n"),
+            ("-2, 3", "-8\n"),
+            ("4, 0.5", "2\n"),
+            ("2, -1", "0.5\n"),
+            (
+                "-8, 1 / 3",
+                "pow() can not raise a negative number to a fractional power.\n",
+            ),
+            ("0, -1", "pow() can not raise 0 to a negative power.\n"),
+            ("10, 400", "pow() result is too large to represent.\n"),
+            ("10, -400", "0\n"),
+        ] {
+            let output = testing::run(&format!("print pow({args});"));
+
+            assert!(output.ends_with(expected), "pow({args}) gave {output:?}");
+        }
+    }
+
+    #[test]
+    fn pow_expects_numbers() {
+        assert!(testing::run("pow(\"2\", 2);").contains("pow() expects numbers."));
+    }
+
+    #[test]
+    fn char_at_and_substr_index_by_character() {
+        assert_eq!(
+            testing::run(
+                "print charAt(\"héllo\", 1); print charAt(\"abc\", 2); \
+                 print substr(\"héllo→x\", 1, 6); print substr(\"abc\", 0, 0);"
+            ),
+            "é\nc\néllo→\n\n"
+        );
+    }
+
+    #[test]
+    fn char_at_rejects_bad_indexes() {
+        for (source, message) in [
+            (
+                "charAt(\"abc\", 3);",
+                "index 3 is out of range for a string of length 3.",
+            ),
+            (
+                "charAt(\"abc\", -1);",
+                "expects a whole number index but got -1.",
+            ),
+            (
+                "charAt(\"abc\", 1.5);",
+                "expects a whole number index but got 1.5.",
+            ),
+            ("charAt(1, 0);", "charAt() expects a string."),
+        ] {
+            let output = testing::run(source);
+
+            assert!(output.contains(message), "{source}: {output}");
+        }
+    }
+
+    #[test]
+    fn substr_rejects_bad_ranges() {
+        assert!(
+            testing::run("substr(\"abc\", 2, 1);")
+                .contains("substr() range 2 to 1 is out of range for a string of length 3.")
+        );
+        assert!(testing::run("substr(\"abc\", 0, 4);").contains("range 0 to 4 is out of range"));
+    }
+
+    #[test]
+    fn split_and_join_round_trip() {
+        assert_eq!(
+            testing::run(
+                "var l = split(\"a,b,c\", \",\"); print l; print len(l); print join(l, \",\");"
+            ),
+            "[a, b, c]\n3\na,b,c\n"
+        );
+    }
+
+    #[test]
+    fn split_keeps_empty_pieces_and_splits_characters_on_an_empty_separator() {
+        assert_eq!(
+            testing::run(
+                "print len(split(\"a,,b\", \",\")); print len(split(\"\", \",\")); \
+                 print split(\"abc\", \"\");"
+            ),
+            "3\n1\n[a, b, c]\n"
+        );
+    }
+
+    #[test]
+    fn join_converts_values_as_str_does() {
+        assert_eq!(testing::run("print join(range(0, 3), \"+\");"), "0+1+2\n");
+    }
+
+    #[test]
+    fn split_and_join_check_their_arguments() {
+        assert!(
+            testing::run("split(1, \",\");")
+                .contains("split() expects a string and a string separator.")
+        );
+        assert!(testing::run("join(\"a\", \",\");").contains(
+            "join() expects a list and a string separator, list values are converted as by str()."
+        ));
+    }
+}
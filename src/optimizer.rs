@@ -0,0 +1,128 @@
+use crate::statements::Stmt;
+
+// Blocks that declare and defer nothing don't get their own environment, so
+// their statements can be spliced into the enclosing list without changing
+// scoping.
+pub fn flatten(statements: Vec<Stmt>) -> Vec<Stmt> {
+    let mut flattened = Vec::with_capacity(statements.len());
+
+    for stmt in statements {
+        match flatten_stmt(stmt) {
+            Stmt::Block { statements, .. } if !statements.iter().any(Stmt::is_scoped) => {
+                flattened.extend(statements)
+            }
+            stmt => flattened.push(stmt),
+        }
+    }
+
+    flattened
+}
+
+fn flatten_stmt(stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::Block { statements, .. } => {
+            let mut statements = flatten(statements);
+
+            if statements.len() == 1 && !statements[0].is_scoped() {
+                statements.remove(0)
+            } else {
+                Stmt::block(statements)
+            }
+        }
+        Stmt::While {
+            condition,
+            body,
+            increment,
+        } => Stmt::While {
+            condition,
+            body: Box::new(flatten_stmt(*body)),
+            increment,
+        },
+        Stmt::DoWhile { body, condition } => Stmt::DoWhile {
+            body: Box::new(flatten_stmt(*body)),
+            condition,
+        },
+        Stmt::Conditional {
+            condition,
+            then_branch,
+            else_branch,
+        } => Stmt::Conditional {
+            condition,
+            then_branch: Box::new(flatten_stmt(*then_branch)),
+            else_branch: else_branch.map(|branch| Box::new(flatten_stmt(*branch))),
+        },
+        Stmt::Function {
+            name,
+            params,
+            body,
+            source,
+            line,
+            column,
+        } => Stmt::Function {
+            name,
+            params,
+            body: flatten(body),
+            source,
+            line,
+            column,
+        },
+        stmt => stmt,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::flatten;
+    use crate::{printer, testing};
+
+    fn flat(source: &str) -> Vec<String> {
+        flatten(testing::parse(source))
+            .iter()
+            .map(printer::print_stmt)
+            .collect()
+    }
+
+    #[test]
+    fn splices_blocks_that_declare_nothing() {
+        assert_eq!(
+            flat("{ print 1; { print 2; } } print 3;"),
+            ["(print 1)", "(print 2)", "(print 3)"]
+        );
+    }
+
+    #[test]
+    fn keeps_blocks_that_declare() {
+        assert_eq!(
+            flat("{ var a = 1; { print a; } }"),
+            ["(block (var a 1) (print a))"]
+        );
+    }
+
+    #[test]
+    fn unwraps_single_statement_loop_bodies() {
+        assert_eq!(
+            flat("for (var i = 0; i < 2; i = i + 1) { print i; }"),
+            ["(block (var i 0) (while (< i 2) (print i) (= i (+ i 1))))"]
+        );
+    }
+
+    #[test]
+    fn flattened_loops_behave_the_same() {
+        assert_eq!(
+            testing::run(
+                "var i = \"outer\"; \
+                 for (var i = 0; i < 3; i = i + 1) { { print i; } } \
+                 print i;"
+            ),
+            "0\n1\n2\nouter\n"
+        );
+    }
+
+    #[test]
+    fn keeps_blocks_that_defer() {
+        assert_eq!(
+            flat("{ defer print 1; print 2; } print 3;"),
+            ["(block (defer (print 1)) (print 2))", "(print 3)"]
+        );
+    }
+}
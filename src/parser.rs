@@ -1,6 +1,7 @@
 use crate::{
     error::{Error, ErrorType},
     expressions::{Expr, Literal},
+    natives,
     statements::Stmt,
     tokens::Token,
 };
@@ -13,54 +14,168 @@ use crate::{
 // unary          → ( "!" | "-" ) unary | primary ;
 // primary        → NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")" ;
 
+// Each nested statement or expression costs native stack in the parser, the
+// resolver and the interpreter, so input nested deeper than this is reported
+// instead of overflowing it.
+const MAX_NESTING: usize = 128;
+
 pub struct Parser<'src> {
     tokens: Vec<Token>,
+    source: Vec<Vec<char>>,
+    first_line: usize,
     error: &'src Error,
     current: usize,
-    in_function: bool,
+    in_block_expression: bool,
+    in_statement: bool,
+    nesting: usize,
+    too_deep: bool,
+    next_id: usize,
 }
 
 impl Parser<'_> {
     pub fn new<'src>(error: &'src Error) -> Parser<'src> {
         Parser {
             tokens: Vec::new(),
+            source: Vec::new(),
+            first_line: 1,
             error,
             current: 0,
-            in_function: false,
+            in_block_expression: false,
+            in_statement: false,
+            nesting: 0,
+            too_deep: false,
+            next_id: 0,
+        }
+    }
+
+    fn token(&self, index: usize) -> Token {
+        // Reading past the end keeps yielding the trailing Eof token.
+        match self.tokens.get(index).or(self.tokens.last()) {
+            Some(token) => token.clone(),
+            None => Token::Eof { line: 1, column: 0 },
         }
     }
 
     fn peek(&self) -> Token {
-        self.tokens.get(self.current).unwrap().clone()
+        self.token(self.current)
     }
 
     fn is_end(&self) -> bool {
-        if let Token::Eof { .. } = self.peek() {
-            true
-        } else {
-            false
-        }
+        matches!(self.peek(), Token::Eof { .. })
     }
 
-    pub fn parse(&mut self, tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<Stmt>> {
+    pub fn start_at_line(&mut self, line: usize) {
+        self.first_line = line;
+    }
+
+    pub fn parse(&mut self, tokens: Vec<Token>, source: &str) -> Result<Vec<Stmt>, Vec<Stmt>> {
         self.current = 0;
+        self.nesting = 0;
+        self.too_deep = false;
 
         self.tokens = tokens;
+        self.source = source.lines().map(|line| line.chars().collect()).collect();
 
         let mut stmts: Vec<Stmt> = Vec::new();
 
         while !self.is_end() {
-            stmts.push(match self.parse_token() {
-                Ok(stmt) => stmt,
-                Err(_) => return Err(stmts),
-            });
+            match self.parse_token() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(_) => self.recover(),
+            }
+        }
+
+        if self.error.had_errors() {
+            Err(stmts)
+        } else {
+            Ok(stmts)
+        }
+    }
+
+    fn source_between(
+        &self,
+        (start_line, start_column): (&usize, &usize),
+        (end_line, end_column): (usize, usize),
+    ) -> String {
+        let mut text = String::new();
+
+        for line in *start_line..=end_line {
+            let chars = match self.source.get(line - self.first_line) {
+                Some(chars) => chars,
+                None => break,
+            };
+
+            let from = if line == *start_line {
+                start_column - 1
+            } else {
+                0
+            };
+            let to = if line == end_line {
+                end_column
+            } else {
+                chars.len()
+            };
+
+            if line != *start_line {
+                text.push('\n');
+            }
+
+            text.extend(chars.iter().take(to).skip(from));
+        }
+
+        text
+    }
+
+    // The line and column of the last character of a token. Numbers and
+    // strings are measured in the source, since their values don't keep the
+    // text they were written as.
+    fn end_of(&self, token: &Token) -> (usize, usize) {
+        let (line, column) = token.location();
+        let (line, column) = (*line, *column);
+
+        match token {
+            Token::Number { .. } => {
+                let chars = self
+                    .source
+                    .get(line - self.first_line)
+                    .map_or(&[][..], |chars| chars);
+                let digits = |from: usize| {
+                    chars
+                        .iter()
+                        .skip(from)
+                        .take_while(|c| c.is_ascii_digit() || **c == '_')
+                        .count()
+                };
+
+                let mut length = digits(column - 1);
+                if chars.get(column - 1 + length) == Some(&'.') {
+                    length += 1 + digits(column + length);
+                }
+
+                (line, column + length.max(1) - 1)
+            }
+            Token::String { value, .. } => match value.rsplit_once('\n') {
+                Some((before, last)) => (
+                    line + before.matches('\n').count() + 1,
+                    last.chars().count() + 1,
+                ),
+                None => (line, column + value.chars().count() + 1),
+            },
+            _ => (line, column + token.width().max(1) - 1),
         }
+    }
+
+    fn variable(&mut self, name: Token) -> Expr {
+        self.next_id += 1;
 
-        Ok(stmts)
+        Expr::Variable {
+            name,
+            id: self.next_id,
+        }
     }
 
     fn previous(&self) -> Token {
-        self.tokens.get(self.current - 1).unwrap().clone()
+        self.token(self.current.saturating_sub(1))
     }
 
     fn synchronize(&mut self) {
@@ -76,6 +191,9 @@ impl Parser<'_> {
                 | Token::For { .. }
                 | Token::If { .. }
                 | Token::While { .. }
+                | Token::Do { .. }
+                | Token::Defer { .. }
+                | Token::Const { .. }
                 | Token::Print { .. }
                 | Token::Return { .. } => return,
                 _ => (),
@@ -85,12 +203,25 @@ impl Parser<'_> {
         }
     }
 
+    fn recover(&mut self) {
+        self.synchronize();
+
+        if let Token::Semicolon { .. } = self.peek() {
+            self.current += 1;
+        }
+    }
+
     fn check_semicolon(&mut self, message: &str) -> bool {
         let prev = self.previous();
 
         if let Token::Semicolon { .. } = self.peek() {
             self.current += 1;
             true
+        } else if let Token::RightBrace { .. } = self.peek()
+            && self.in_block_expression
+        {
+            // The value of a block expression can leave out its ';'.
+            true
         } else {
             self.error
                 .report(prev.location(), ErrorType::ParserError, message);
@@ -99,13 +230,41 @@ impl Parser<'_> {
         }
     }
 
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, ()>) -> Result<T, ()> {
+        if self.nesting >= MAX_NESTING {
+            self.error.report(
+                self.peek().location(),
+                ErrorType::ParserError,
+                &format!("Can not nest more than {MAX_NESTING} levels deep."),
+            );
+
+            // The enclosing levels can't be closed properly any more, so the
+            // rest of the input is given up on rather than reported on.
+            self.current = self.tokens.len();
+            self.too_deep = true;
+            return Err(());
+        }
+
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
+
+        result
+    }
+
     fn parse_token(&mut self) -> Result<Stmt, ()> {
+        self.nested(Self::statement)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ()> {
         match self.peek() {
             Token::Identifier { .. } => {
-                let expr = self.assignment()?;
+                let expr = self.statement_expression()?;
 
-                if self.in_function {
-                    Ok(Stmt::Expression { expr })
+                if let Token::Comma { .. } = self.peek()
+                    && let Expr::Variable { .. } = expr
+                {
+                    self.multi_assign(expr)
                 } else {
                     if !self.check_semicolon("Expect ';' after expression.") {
                         return Err(());
@@ -117,7 +276,7 @@ impl Parser<'_> {
             Token::Print { .. } => {
                 self.current += 1;
 
-                let expr = self.assignment()?;
+                let expr = self.expression()?;
 
                 if self.check_semicolon("Expected ';' after statement.") {
                     return Ok(Stmt::Print { expr });
@@ -134,16 +293,107 @@ impl Parser<'_> {
 
                 Err(())
             }
+            Token::Continue { line, column } => {
+                self.current += 1;
+
+                if self.check_semicolon("Expected ';' after statement.") {
+                    return Ok(Stmt::Continue { line, column });
+                }
+
+                Err(())
+            }
+            Token::Defer { line, column } => {
+                self.current += 1;
+
+                if let token @ (Token::Var { .. } | Token::Fun { .. }) = self.peek() {
+                    self.error.report(
+                        token.location(),
+                        ErrorType::ParserError,
+                        "Can not defer a declaration.",
+                    );
+                    self.synchronize();
+                    return Err(());
+                }
+
+                let stmt = Box::new(self.parse_token()?);
+
+                Ok(Stmt::Defer { stmt, line, column })
+            }
             Token::Var { .. } => {
                 self.current += 1;
 
+                let mut declarations = Vec::new();
+
+                loop {
+                    let token = self.peek();
+                    let (name, line, column);
+
+                    self.current += 1;
+
+                    if let Token::Identifier {
+                        value,
+                        line: l,
+                        column: c,
+                    } = token
+                    {
+                        (name, line, column) = (value, l, c);
+                    } else {
+                        self.error.report(
+                            token.location(),
+                            ErrorType::ParserError,
+                            "Expected identifier.",
+                        );
+                        return Err(());
+                    }
+
+                    let expr = if let Token::Equal { .. } = self.peek() {
+                        self.current += 1;
+                        self.expression()?
+                    } else {
+                        Expr::Literal {
+                            value: Literal::Nil,
+                        }
+                    };
+
+                    declarations.push(Stmt::Var {
+                        name,
+                        expr,
+                        line,
+                        column,
+                    });
+
+                    if let Token::Comma { .. } = self.peek() {
+                        self.current += 1;
+                    } else {
+                        break;
+                    }
+                }
+
+                if !self.check_semicolon("Expected ';' after expression.") {
+                    return Err(());
+                }
+
+                if declarations.len() == 1 {
+                    Ok(declarations.remove(0))
+                } else {
+                    Ok(Stmt::MultiVar { declarations })
+                }
+            }
+            Token::Const { .. } => {
+                self.current += 1;
+
                 let token = self.peek();
-                let name: String;
+                let (name, line, column);
 
                 self.current += 1;
 
-                if let Token::Identifier { value, .. } = token {
-                    name = value;
+                if let Token::Identifier {
+                    value,
+                    line: l,
+                    column: c,
+                } = token
+                {
+                    (name, line, column) = (value, l, c);
                 } else {
                     self.error.report(
                         token.location(),
@@ -156,25 +406,29 @@ impl Parser<'_> {
                 if let Token::Equal { .. } = self.peek() {
                     self.current += 1;
                 } else {
-                    if self.check_semicolon("Expected ';' after expression.") {
-                        return Ok(Stmt::Var {
-                            name,
-                            expr: Expr::Literal {
-                                value: Literal::Nil,
-                            },
-                        });
-                    }
+                    self.error.report(
+                        self.previous().location(),
+                        ErrorType::ParserError,
+                        "Expected '=' after constant name.",
+                    );
+                    self.synchronize();
+                    return Err(());
                 }
 
-                let expr = self.assignment()?;
+                let expr = self.expression()?;
 
-                if self.check_semicolon("Expected ';' after expression.") {
-                    return Ok(Stmt::Var { name, expr });
+                if !self.check_semicolon("Expected ';' after expression.") {
+                    return Err(());
                 }
 
-                Err(())
+                Ok(Stmt::Const {
+                    name,
+                    expr,
+                    line,
+                    column,
+                })
             }
-            Token::LeftBrace { .. } => {
+            Token::LeftBrace { line, column } => {
                 self.current += 1;
 
                 let mut stmts: Vec<Stmt> = Vec::new();
@@ -183,19 +437,18 @@ impl Parser<'_> {
                     if let Token::RightBrace { .. } = self.peek() {
                         break;
                     } else {
-                        stmts.push(self.parse_token()?);
+                        match self.parse_token() {
+                            Ok(stmt) => stmts.push(stmt),
+                            Err(_) => self.recover(),
+                        }
                     }
                 }
 
                 if let Token::RightBrace { .. } = self.peek() {
                     self.current += 1;
-                    Ok(Stmt::Block { statements: stmts })
+                    Ok(Stmt::block(stmts))
                 } else {
-                    self.error.report(
-                        self.peek().location(),
-                        ErrorType::ParserError,
-                        "Expected '}' after block.",
-                    );
+                    self.unterminated_block((&line, &column));
                     self.synchronize();
                     Err(())
                 }
@@ -206,7 +459,7 @@ impl Parser<'_> {
                 if let Token::LeftParen { .. } = self.peek() {
                     self.current += 1;
 
-                    let condition = self.assignment()?;
+                    let condition = self.expression()?;
 
                     if let Token::RightParen { .. } = self.peek() {
                         self.current += 1;
@@ -254,14 +507,18 @@ impl Parser<'_> {
                 if let Token::LeftParen { .. } = self.peek() {
                     self.current += 1;
 
-                    let condition = self.assignment()?;
+                    let condition = self.expression()?;
 
                     if let Token::RightParen { .. } = self.peek() {
                         self.current += 1;
 
                         let body = Box::new(self.parse_token()?);
 
-                        return Ok(Stmt::While { condition, body });
+                        return Ok(Stmt::While {
+                            condition,
+                            body,
+                            increment: None,
+                        });
                     } else {
                         self.error.report(
                             self.peek().location(),
@@ -280,12 +537,72 @@ impl Parser<'_> {
 
                 Err(())
             }
+            Token::Do { .. } => {
+                self.current += 1;
+
+                let body = Box::new(self.parse_token()?);
+
+                if let Token::While { .. } = self.peek() {
+                    self.current += 1;
+                } else {
+                    self.error.report(
+                        self.previous().location(),
+                        ErrorType::ParserError,
+                        "Expected 'while' after do body.",
+                    );
+                    self.synchronize();
+                    return Err(());
+                }
+
+                if let Token::LeftParen { .. } = self.peek() {
+                    self.current += 1;
+                } else {
+                    self.error.report(
+                        self.previous().location(),
+                        ErrorType::ParserError,
+                        "Expected '(' after 'while'.",
+                    );
+                    self.synchronize();
+                    return Err(());
+                }
+
+                let condition = self.expression()?;
+
+                if let Token::RightParen { .. } = self.peek() {
+                    self.current += 1;
+                } else {
+                    self.error.report(
+                        self.peek().location(),
+                        ErrorType::ParserError,
+                        "Expected ')' after condition.",
+                    );
+                    self.synchronize();
+                    return Err(());
+                }
+
+                if !self.check_semicolon("Expected ';' after do-while condition.") {
+                    return Err(());
+                }
+
+                Ok(Stmt::DoWhile { body, condition })
+            }
             Token::For { .. } => {
                 self.current += 1;
 
                 if let Token::LeftParen { .. } = self.peek() {
                     self.current += 1;
 
+                    if let Token::Identifier {
+                        value,
+                        line,
+                        column,
+                    } = self.peek()
+                        && let Token::In { .. } = self.token(self.current + 1)
+                    {
+                        self.current += 2;
+                        return self.for_in(value, line, column);
+                    }
+
                     let init = match self.peek() {
                         Token::Semicolon { .. } => {
                             self.current += 1;
@@ -302,7 +619,7 @@ impl Parser<'_> {
                             }
                         }
                         _ => {
-                            let condition = self.assignment()?;
+                            let condition = self.expression()?;
 
                             if self.check_semicolon("Expected ';' after expression.") {
                                 condition
@@ -317,7 +634,7 @@ impl Parser<'_> {
                             self.current += 1;
                             None
                         }
-                        _ => Some(self.assignment()?),
+                        _ => Some(self.statement_expression()?),
                     };
 
                     if let Token::RightParen { .. } = self.peek() {
@@ -332,23 +649,14 @@ impl Parser<'_> {
                         return Err(());
                     }
 
-                    let mut body = self.parse_token()?;
-
-                    if let Some(incr) = incr {
-                        body = Stmt::Block {
-                            statements: vec![body, Stmt::Expression { expr: incr }],
-                        };
-                    }
-
-                    body = Stmt::While {
+                    let mut body = Stmt::While {
                         condition,
-                        body: Box::new(body),
+                        body: Box::new(self.parse_token()?),
+                        increment: incr,
                     };
 
                     if let Some(init) = init {
-                        body = Stmt::Block {
-                            statements: vec![init, body],
-                        };
+                        body = Stmt::block(vec![init, body]);
                     }
 
                     Ok(body)
@@ -363,216 +671,600 @@ impl Parser<'_> {
                 }
             }
             Token::Fun { .. } => {
-                self.current += 1;
-
-                let mut name = None;
-
-                if let Token::Identifier { value, .. } = self.peek() {
-                    self.current += 1;
-                    name = Some(value);
-                }
-
-                if let Token::LeftParen { .. } = self.peek() {
-                    self.current += 1;
-
-                    let mut params = Vec::new();
-
-                    while !self.is_end() {
-                        let token = self.peek();
-
-                        if let Token::RightParen { .. } = token {
-                            self.current += 1;
+                let Some(Token::Identifier {
+                    value: name,
+                    line,
+                    column,
+                }) = self.tokens.get(self.current + 1).cloned()
+                else {
+                    let expr = self.expression()?;
 
-                            let stmt = self.parse_token()?;
-
-                            match stmt {
-                                Stmt::Block { statements } => {
-                                    return Ok(Stmt::Function {
-                                        name,
-                                        params,
-                                        body: statements,
-                                    })
-                                }
-                                _ => {
-                                    self.error.report(
-                                        self.peek().location(),
-                                        ErrorType::ParserError,
-                                        "Expected block after function declaration.",
-                                    );
-                                    self.synchronize();
-                                    return Err(());
-                                }
-                            }
-                        } else {
-                            if params.len() >= 255 {
-                                self.error.report(
-                                    token.location(),
-                                    ErrorType::ParserError,
-                                    "Can not have more than 255 parameters.",
-                                );
-                            }
-
-                            if let Token::Identifier { value, .. } = self.peek() {
-                                self.current += 1;
-                                params.push(value);
-                            } else {
-                                self.error.report(
-                                    token.location(),
-                                    ErrorType::ParserError,
-                                    "Expected identifier.",
-                                );
-                            }
+                    if !self.check_semicolon("Expect ';' after expression.") {
+                        return Err(());
+                    }
 
-                            let token = self.peek();
+                    return Ok(Stmt::Expression { expr });
+                };
 
-                            if let Token::RightParen { .. } = token {
-                                continue;
-                            }
+                self.current += 2;
 
-                            if let Token::Comma { .. } = token {
-                                self.current += 1;
-                            } else {
-                                self.error.report(
-                                    token.location(),
-                                    ErrorType::ParserError,
-                                    "Expected ')' or ',' after parameter.",
-                                );
-                                self.synchronize();
-                                return Err(());
-                            }
-                        }
-                    }
+                let (params, body, source) = self.function()?;
 
-                    self.error.report(
-                        self.peek().location(),
-                        ErrorType::ParserError,
-                        "Expected ')' after parameters.",
-                    );
-                    self.synchronize();
-                } else {
-                    self.error.report(
-                        self.previous().location(),
-                        ErrorType::ParserError,
-                        "Expected '(' after function name.",
-                    );
-                    self.synchronize();
+                if source.starts_with("=>")
+                    && !self.check_semicolon("Expected ';' after function body.")
+                {
+                    return Err(());
                 }
 
-                Err(())
+                Ok(Stmt::Function {
+                    name,
+                    params,
+                    body,
+                    source,
+                    line,
+                    column,
+                })
             }
-            Token::Return { .. } => {
+            Token::Return { line, column } => {
                 self.current += 1;
 
-                let expr = self.assignment()?;
+                let expr = if let Token::Semicolon { .. } = self.peek() {
+                    Expr::Literal {
+                        value: Literal::Nil,
+                    }
+                } else {
+                    self.expression()?
+                };
 
                 if self.check_semicolon("Expected ';' after return value.") {
-                    Ok(Stmt::Return { expr })
+                    Ok(Stmt::Return { expr, line, column })
                 } else {
                     Err(())
                 }
             }
             _ => Ok(Stmt::Expression {
-                expr: self.assignment()?,
+                expr: self.expression()?,
             }),
         }
     }
 
-    fn assignment(&mut self) -> Result<Expr, ()> {
-        let expr = self.or()?;
+    // for (name in iterable) body
+    //
+    // runs as
+    //
+    // { var list = iterable; var index = 0;
+    //   for (; index < len(list); index = index + 1) { var name = list[index]; body } }
+    //
+    // where list and index can't be named from Lox code.
+    fn for_in(&mut self, name: String, line: usize, column: usize) -> Result<Stmt, ()> {
+        let keyword = self.previous();
+        let iterable = self.expression()?;
+
+        if let Token::RightParen { .. } = self.peek() {
+            self.current += 1;
+        } else {
+            self.error.report(
+                self.peek().location(),
+                ErrorType::ParserError,
+                "Expected ')' after loop iterable.",
+            );
+            self.synchronize();
+            return Err(());
+        }
 
-        if let Expr::Variable { name } = &expr {
-            if let Token::Identifier { .. } = name {
-                match self.peek() {
-                    Token::Equal { .. } => {
-                        self.current += 1;
+        let body = self.parse_token()?;
+
+        let hidden = |value: &str| Token::Identifier {
+            value: value.to_owned(),
+            line,
+            column,
+        };
+        let (list, index) = (" list", " index");
+
+        let condition = Expr::Binary {
+            left: Box::new(self.variable(hidden(index))),
+            operator: Token::Less { line, column },
+            right: Box::new(Expr::Call {
+                callee: Box::new(Expr::Literal {
+                    value: natives::len_builtin(),
+                }),
+                paren: keyword.clone(),
+                arguments: vec![self.variable(hidden(list))],
+            }),
+        };
+
+        let element = Expr::Index {
+            object: Box::new(self.variable(hidden(list))),
+            index: Box::new(self.variable(hidden(index))),
+            bracket: keyword,
+        };
+
+        let increment = Expr::Binary {
+            left: Box::new(self.variable(hidden(index))),
+            operator: Token::Plus { line, column },
+            right: Box::new(Expr::Literal {
+                value: Literal::Number(1.0),
+            }),
+        };
+
+        self.next_id += 1;
+
+        let increment = Expr::Assign {
+            name: hidden(index),
+            value: Box::new(increment),
+            id: self.next_id,
+        };
+
+        Ok(Stmt::block(vec![
+            Stmt::Var {
+                name: list.to_owned(),
+                expr: iterable,
+                line,
+                column,
+            },
+            Stmt::Var {
+                name: index.to_owned(),
+                expr: Expr::Literal {
+                    value: Literal::Number(0.0),
+                },
+                line,
+                column,
+            },
+            Stmt::While {
+                condition,
+                body: Box::new(Stmt::block(vec![
+                    Stmt::Var {
+                        name,
+                        expr: element,
+                        line,
+                        column,
+                    },
+                    body,
+                ])),
+                increment: Some(increment),
+            },
+        ]))
+    }
 
-                        let value = Box::new(self.assignment()?);
+    // After a '{' in an expression, a ':' before the first ';' or the closing
+    // '}' makes it a map literal, and anything else is a block. '{}' is an
+    // empty map.
+    fn is_map(&self) -> bool {
+        let mut depth = 0;
 
-                        return Ok(Expr::Assign {
-                            name: name.clone(),
-                            value,
-                        });
-                    }
-                    Token::PlusEqual { line, column } => {
-                        self.current += 1;
+        for token in &self.tokens[self.current..] {
+            match token {
+                Token::LeftParen { .. } | Token::LeftBrace { .. } | Token::LeftBracket { .. } => {
+                    depth += 1
+                }
+                Token::RightBrace { .. } if depth == 0 => {
+                    return matches!(self.peek(), Token::RightBrace { .. });
+                }
+                Token::RightParen { .. }
+                | Token::RightBrace { .. }
+                | Token::RightBracket { .. } => depth -= 1,
+                Token::Colon { .. } if depth == 0 => return true,
+                Token::Semicolon { .. } if depth == 0 => return false,
+                _ => (),
+            }
+        }
 
-                        let value = Box::new(self.assignment()?);
+        false
+    }
 
-                        return Ok(Expr::Assign {
-                            name: name.clone(),
-                            value: Box::new(Expr::Binary {
-                                left: Box::new(Expr::Variable { name: name.clone() }),
-                                operator: Token::Plus { line, column },
-                                right: value,
-                            }),
-                        });
-                    }
-                    Token::MinusEqual { line, column } => {
-                        self.current += 1;
+    // Pointing at the end of the file is no help in finding a missing '}', so
+    // the error is reported at the brace that was never closed.
+    fn unterminated_block(&self, location: (&usize, &usize)) {
+        if self.too_deep {
+            return;
+        }
 
-                        let value = Box::new(self.assignment()?);
+        self.error.report(
+            location,
+            ErrorType::ParserError,
+            &format!("Unterminated block opened at line {}.", location.0),
+        );
+    }
 
-                        return Ok(Expr::Assign {
-                            name: name.clone(),
-                            value: Box::new(Expr::Binary {
-                                left: Box::new(Expr::Variable { name: name.clone() }),
-                                operator: Token::Minus { line, column },
-                                right: value,
-                            }),
-                        });
-                    }
-                    Token::StarEqual { line, column } => {
-                        self.current += 1;
+    fn function(&mut self) -> Result<(Vec<String>, Vec<Stmt>, String), ()> {
+        if let Token::LeftParen { .. } = self.peek() {
+            self.current += 1;
 
-                        let value = Box::new(self.assignment()?);
+            let mut params = Vec::new();
 
-                        return Ok(Expr::Assign {
-                            name: name.clone(),
-                            value: Box::new(Expr::Binary {
-                                left: Box::new(Expr::Variable { name: name.clone() }),
-                                operator: Token::Star { line, column },
+            while !self.is_end() {
+                let token = self.peek();
+
+                if let Token::RightParen { .. } = token {
+                    self.current += 1;
+
+                    let open = self.peek();
+
+                    if let Token::FatArrow { line, column } = open {
+                        self.current += 1;
+
+                        let expr = self.expression()?;
+                        let source =
+                            self.source_between(open.location(), self.end_of(&self.previous()));
+
+                        return Ok((params, vec![Stmt::Return { expr, line, column }], source));
+                    }
+
+                    let in_block_expression =
+                        std::mem::replace(&mut self.in_block_expression, false);
+                    let stmt = self.parse_token();
+                    self.in_block_expression = in_block_expression;
+
+                    match stmt? {
+                        Stmt::Block { statements, .. } => {
+                            let source =
+                                self.source_between(open.location(), self.end_of(&self.previous()));
+
+                            return Ok((params, statements, source));
+                        }
+                        _ => {
+                            self.error.report(
+                                self.peek().location(),
+                                ErrorType::ParserError,
+                                "Expected block after function declaration.",
+                            );
+                            self.synchronize();
+                            return Err(());
+                        }
+                    }
+                } else {
+                    if params.len() >= 255 {
+                        self.error.report(
+                            token.location(),
+                            ErrorType::ParserError,
+                            "Can not have more than 255 parameters.",
+                        );
+                    }
+
+                    if let Token::Identifier { value, .. } = self.peek() {
+                        self.current += 1;
+                        params.push(value);
+                    } else {
+                        self.error.report(
+                            token.location(),
+                            ErrorType::ParserError,
+                            "Expected identifier.",
+                        );
+                    }
+
+                    let token = self.peek();
+
+                    if let Token::RightParen { .. } = token {
+                        continue;
+                    }
+
+                    if let Token::Comma { .. } = token {
+                        self.current += 1;
+                    } else {
+                        self.error.report(
+                            token.location(),
+                            ErrorType::ParserError,
+                            "Expected ')' or ',' after parameter.",
+                        );
+                        self.synchronize();
+                        return Err(());
+                    }
+                }
+            }
+
+            self.error.report(
+                self.peek().location(),
+                ErrorType::ParserError,
+                "Expected ')' after parameters.",
+            );
+            self.synchronize();
+        } else {
+            self.error.report(
+                self.previous().location(),
+                ErrorType::ParserError,
+                "Expected '(' after function name.",
+            );
+            self.synchronize();
+        }
+
+        Err(())
+    }
+
+    fn multi_assign(&mut self, first: Expr) -> Result<Stmt, ()> {
+        let mut targets = vec![first];
+
+        while let Token::Comma { .. } = self.peek() {
+            self.current += 1;
+
+            let token = self.peek();
+
+            if let Token::Identifier { .. } = token {
+                self.current += 1;
+                let target = self.variable(token);
+                targets.push(target);
+            } else {
+                self.error.report(
+                    token.location(),
+                    ErrorType::ParserError,
+                    "Expected variable name after ','.",
+                );
+                self.synchronize();
+                return Err(());
+            }
+        }
+
+        if let Token::Equal { .. } = self.peek() {
+            self.current += 1;
+        } else {
+            self.error.report(
+                self.peek().location(),
+                ErrorType::ParserError,
+                "Expected '=' after assignment targets.",
+            );
+            self.synchronize();
+            return Err(());
+        }
+
+        let mut values = vec![self.expression()?];
+
+        while let Token::Comma { .. } = self.peek() {
+            self.current += 1;
+            values.push(self.expression()?);
+        }
+
+        if !self.check_semicolon("Expect ';' after assignment.") {
+            return Err(());
+        }
+
+        Ok(Stmt::MultiAssign { targets, values })
+    }
+
+    // Parses an expression whose value is thrown away. Only there can `++` and
+    // `--` be used, since they desugar to an assignment that yields the new
+    // value rather than the old one.
+    fn statement_expression(&mut self) -> Result<Expr, ()> {
+        self.in_statement = true;
+        self.expression()
+    }
+
+    fn expression(&mut self) -> Result<Expr, ()> {
+        self.nested(Self::assignment)
+    }
+
+    fn assignment(&mut self) -> Result<Expr, ()> {
+        let in_statement = std::mem::replace(&mut self.in_statement, false);
+        let expr = self.coalesce()?;
+
+        if let Expr::Index {
+            object,
+            index,
+            bracket,
+        } = &expr
+            && let Token::Equal { .. } = self.peek()
+        {
+            self.current += 1;
+
+            return Ok(Expr::Set {
+                object: object.clone(),
+                index: index.clone(),
+                value: Box::new(self.expression()?),
+                bracket: bracket.clone(),
+            });
+        }
+
+        if let Expr::Index {
+            object,
+            index,
+            bracket,
+        } = &expr
+            && let Some(operator) = compound_operator(&self.peek())
+        {
+            self.current += 1;
+
+            let value = self.expression()?;
+
+            return Ok(self.compound_set(object, index, bracket, operator, value));
+        }
+
+        if let Expr::Variable { name, id } = &expr
+            && let Token::Identifier { .. } = name
+        {
+            match self.peek() {
+                Token::Equal { .. } => {
+                    self.current += 1;
+
+                    let value = Box::new(self.expression()?);
+
+                    return Ok(Expr::Assign {
+                        name: name.clone(),
+                        value,
+                        id: *id,
+                    });
+                }
+                token @ (Token::PlusPlus { .. } | Token::MinusMinus { .. }) if !in_statement => {
+                    self.error.report(
+                        token.location(),
+                        ErrorType::ParserError,
+                        &format!("Can only use '{}' as a statement.", token.symbol()),
+                    );
+                    self.synchronize();
+                    return Err(());
+                }
+                Token::PlusPlus { line, column } | Token::MinusMinus { line, column } => {
+                    self.current += 1;
+
+                    let operator = match self.previous() {
+                        Token::PlusPlus { .. } => Token::Plus { line, column },
+                        _ => Token::Minus { line, column },
+                    };
+
+                    return Ok(Expr::Assign {
+                        name: name.clone(),
+                        value: Box::new(Expr::Binary {
+                            left: Box::new(expr.clone()),
+                            operator,
+                            right: Box::new(Expr::Literal {
+                                value: Literal::Number(1.0),
+                            }),
+                        }),
+                        id: *id,
+                    });
+                }
+                token => {
+                    if let Some(operator) = compound_operator(&token) {
+                        self.current += 1;
+
+                        let value = Box::new(self.expression()?);
+
+                        return Ok(Expr::Assign {
+                            name: name.clone(),
+                            value: Box::new(Expr::Binary {
+                                left: Box::new(expr.clone()),
+                                operator,
                                 right: value,
                             }),
+                            id: *id,
                         });
                     }
-                    _ => (),
                 }
-            } else {
-                panic!("Invalid identifier.");
             }
         }
 
+        match self.peek() {
+            token @ (Token::PlusPlus { .. } | Token::MinusMinus { .. }) => {
+                self.error.report(
+                    token.location(),
+                    ErrorType::ParserError,
+                    &format!("Can only apply '{}' to a variable.", token.symbol()),
+                );
+                self.synchronize();
+                Err(())
+            }
+            token @ (Token::Equal { .. }
+            | Token::PlusEqual { .. }
+            | Token::MinusEqual { .. }
+            | Token::StarEqual { .. }
+            | Token::SlashEqual { .. }) => {
+                self.error.report(
+                    token.location(),
+                    ErrorType::ParserError,
+                    "Invalid assignment target.",
+                );
+                self.synchronize();
+                Err(())
+            }
+            _ => Ok(expr),
+        }
+    }
+
+    // object[index] op= value
+    //
+    // runs as
+    //
+    // { var object = object; var index = index; object[index] = object[index] op value; }
+    //
+    // so the object and the index are only evaluated once, and neither name
+    // can be used from Lox code.
+    fn compound_set(
+        &mut self,
+        object: &Expr,
+        index: &Expr,
+        bracket: &Token,
+        operator: Token,
+        value: Expr,
+    ) -> Expr {
+        let (&line, &column) = bracket.location();
+        let hidden = |value: &str| Token::Identifier {
+            value: value.to_owned(),
+            line,
+            column,
+        };
+        let (object_name, index_name) = (" object", " index");
+
+        let current = Expr::Index {
+            object: Box::new(self.variable(hidden(object_name))),
+            index: Box::new(self.variable(hidden(index_name))),
+            bracket: bracket.clone(),
+        };
+
+        let set = Expr::Set {
+            object: Box::new(self.variable(hidden(object_name))),
+            index: Box::new(self.variable(hidden(index_name))),
+            value: Box::new(Expr::Binary {
+                left: Box::new(current),
+                operator,
+                right: Box::new(value),
+            }),
+            bracket: bracket.clone(),
+        };
+
+        Expr::Block {
+            statements: vec![
+                Stmt::Var {
+                    name: object_name.to_owned(),
+                    expr: object.clone(),
+                    line,
+                    column,
+                },
+                Stmt::Var {
+                    name: index_name.to_owned(),
+                    expr: index.clone(),
+                    line,
+                    column,
+                },
+                Stmt::Expression { expr: set },
+            ],
+            declares: true,
+            brace: bracket.clone(),
+        }
+    }
+
+    fn coalesce(&mut self) -> Result<Expr, ()> {
+        let mut expr = self.or()?;
+
+        while let Token::QuestionQuestion { .. } = self.peek() {
+            self.current += 1;
+
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator: self.previous(),
+                right: Box::new(self.or()?),
+            };
+        }
+
         Ok(expr)
     }
 
     fn or(&mut self) -> Result<Expr, ()> {
-        let expr = self.and()?;
+        let mut expr = self.and()?;
 
-        if let Token::Or { .. } = self.peek() {
+        while let Token::Or { .. } = self.peek() {
             self.current += 1;
-            Ok(Expr::Logical {
+
+            expr = Expr::Logical {
                 left: Box::new(expr),
                 operator: self.previous(),
                 right: Box::new(self.and()?),
-            })
-        } else {
-            Ok(expr)
+            };
         }
+
+        Ok(expr)
     }
 
     fn and(&mut self) -> Result<Expr, ()> {
-        let expr = self.equality()?;
+        let mut expr = self.equality()?;
 
-        if let Token::And { .. } = self.peek() {
+        while let Token::And { .. } = self.peek() {
             self.current += 1;
-            Ok(Expr::Logical {
+
+            expr = Expr::Logical {
                 left: Box::new(expr),
                 operator: self.previous(),
                 right: Box::new(self.equality()?),
-            })
-        } else {
-            Ok(expr)
+            };
         }
+
+        Ok(expr)
     }
 
     fn equality(&mut self) -> Result<Expr, ()> {
@@ -591,11 +1283,75 @@ impl Parser<'_> {
     }
 
     fn comparison(&mut self) -> Result<Expr, ()> {
-        let mut expr = self.term()?;
+        let mut expr = self.bit_or()?;
 
         while let Token::Greater { .. } | Token::GreaterEqual { .. } | Token::Less { .. } | Token::LessEqual { .. } = self.peek() && !self.is_end() {
             self.current += 1;
 
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator: self.previous(),
+                right: Box::new(self.bit_or()?),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_or(&mut self) -> Result<Expr, ()> {
+        let mut expr = self.bit_xor()?;
+
+        while let Token::Pipe { .. } = self.peek() {
+            self.current += 1;
+
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator: self.previous(),
+                right: Box::new(self.bit_xor()?),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr, ()> {
+        let mut expr = self.bit_and()?;
+
+        while let Token::Caret { .. } = self.peek() {
+            self.current += 1;
+
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator: self.previous(),
+                right: Box::new(self.bit_and()?),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr, ()> {
+        let mut expr = self.shift()?;
+
+        while let Token::Ampersand { .. } = self.peek() {
+            self.current += 1;
+
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator: self.previous(),
+                right: Box::new(self.shift()?),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn shift(&mut self) -> Result<Expr, ()> {
+        let mut expr = self.term()?;
+
+        while let Token::LessLess { .. } | Token::GreaterGreater { .. } = self.peek() {
+            self.current += 1;
+
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator: self.previous(),
@@ -639,20 +1395,18 @@ impl Parser<'_> {
     }
 
     fn unary(&mut self) -> Result<Expr, ()> {
-        let mut expr = self.call()?;
-
-        if let Token::Bang { .. } | Token::Minus { .. } = self.peek() && !self.is_end() {
+        if let Token::Bang { .. } | Token::Minus { .. } = self.peek() {
             self.current += 1;
 
             let operator = self.previous();
 
-            expr = Expr::Unary {
+            return Ok(Expr::Unary {
                 operator,
-                right: Box::new(self.unary()?),
-            };
+                right: Box::new(self.nested(Self::unary)?),
+            });
         }
 
-        Ok(expr)
+        self.call()
     }
 
     fn call(&mut self) -> Result<Expr, ()> {
@@ -666,8 +1420,6 @@ impl Parser<'_> {
 
                 let mut arguments = Vec::new();
 
-                self.in_function = true;
-
                 while !self.is_end() {
                     let token = self.peek();
 
@@ -688,7 +1440,20 @@ impl Parser<'_> {
                             );
                         }
 
-                        arguments.push(self.parse_token()?);
+                        if let Token::Break { .. } | Token::Continue { .. } | Token::Return { .. } =
+                            token
+                        {
+                            self.current += 1;
+                            self.error.report(
+                                token.location(),
+                                ErrorType::ParserError,
+                                &format!("Can not use '{}' as an argument.", token.symbol()),
+                            );
+                            self.synchronize();
+                            return Err(());
+                        }
+
+                        arguments.push(self.expression()?);
 
                         let token = self.peek();
 
@@ -710,8 +1475,6 @@ impl Parser<'_> {
                     }
                 }
 
-                self.in_function = false;
-
                 if let Token::RightParen { .. } = self.peek() {
                     self.current += 1;
                 } else {
@@ -723,6 +1486,28 @@ impl Parser<'_> {
                     self.synchronize();
                     return Err(());
                 }
+            } else if let Token::LeftBracket { .. } = paren {
+                self.current += 1;
+
+                let index = self.expression()?;
+
+                if let Token::RightBracket { .. } = self.peek() {
+                    self.current += 1;
+                } else {
+                    self.error.report(
+                        self.peek().location(),
+                        ErrorType::ParserError,
+                        "Expected ']' after index.",
+                    );
+                    self.synchronize();
+                    return Err(());
+                }
+
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    index: Box::new(index),
+                    bracket: paren,
+                };
             } else {
                 break;
             }
@@ -757,10 +1542,22 @@ impl Parser<'_> {
                 value: Literal::Nil,
             }),
 
-            Token::Identifier { .. } => Ok(Expr::Variable { name: token }),
+            Token::Identifier { .. } => Ok(self.variable(token)),
+
+            Token::Fun { line, column } => {
+                let (params, body, source) = self.function()?;
+
+                Ok(Expr::Lambda {
+                    params,
+                    body,
+                    source,
+                    line,
+                    column,
+                })
+            }
 
             Token::LeftParen { .. } => {
-                let mut expr = self.assignment()?;
+                let mut expr = self.expression()?;
 
                 let token = self.peek();
 
@@ -782,7 +1579,108 @@ impl Parser<'_> {
                 Ok(expr)
             }
 
+            Token::LeftBrace { .. } if !self.is_map() => {
+                let in_block_expression = std::mem::replace(&mut self.in_block_expression, true);
+
+                let mut statements = Vec::new();
+
+                while !self.is_end() {
+                    if let Token::RightBrace { .. } = self.peek() {
+                        break;
+                    }
+
+                    match self.parse_token() {
+                        Ok(stmt) => statements.push(stmt),
+                        Err(_) => self.recover(),
+                    }
+                }
+
+                self.in_block_expression = in_block_expression;
+
+                if let Token::RightBrace { .. } = self.peek() {
+                    self.current += 1;
+                } else {
+                    self.unterminated_block(token.location());
+                    self.synchronize();
+                    return Err(());
+                }
+
+                Ok(Expr::Block {
+                    declares: statements.iter().any(Stmt::is_declaration),
+                    statements,
+                    brace: token,
+                })
+            }
+
+            Token::LeftBrace { .. } => {
+                let mut entries = Vec::new();
+
+                while !self.is_end() {
+                    if let Token::RightBrace { .. } = self.peek() {
+                        break;
+                    }
+
+                    let key = self.coalesce()?;
+
+                    if let Token::Colon { .. } = self.peek() {
+                        self.current += 1;
+                    } else {
+                        self.error.report(
+                            self.peek().location(),
+                            ErrorType::ParserError,
+                            "Expected ':' after map key.",
+                        );
+                        self.synchronize();
+                        return Err(());
+                    }
+
+                    entries.push((key, self.expression()?));
+
+                    match self.peek() {
+                        Token::Comma { .. } => self.current += 1,
+                        Token::RightBrace { .. } => break,
+                        next => {
+                            self.error.report(
+                                next.location(),
+                                ErrorType::ParserError,
+                                "Expected '}' or ',' after map entry.",
+                            );
+                            self.synchronize();
+                            return Err(());
+                        }
+                    }
+                }
+
+                if let Token::RightBrace { .. } = self.peek() {
+                    self.current += 1;
+                } else {
+                    self.error.report(
+                        self.peek().location(),
+                        ErrorType::ParserError,
+                        "Expected '}' after map entries.",
+                    );
+                    self.synchronize();
+                    return Err(());
+                }
+
+                Ok(Expr::Map {
+                    entries,
+                    brace: token,
+                })
+            }
+
+            Token::Break { .. } | Token::Continue { .. } | Token::Return { .. } => {
+                self.error.report(
+                    token.location(),
+                    ErrorType::ParserError,
+                    &format!("Can not use '{}' in an expression.", token.symbol()),
+                );
+                self.synchronize();
+                Err(())
+            }
+
             _ => {
+                self.current -= 1;
                 self.error.report(
                     token.location(),
                     ErrorType::ParserError,
@@ -793,3 +1691,521 @@ impl Parser<'_> {
         }
     }
 }
+
+fn compound_operator(token: &Token) -> Option<Token> {
+    match *token {
+        Token::PlusEqual { line, column } => Some(Token::Plus { line, column }),
+        Token::MinusEqual { line, column } => Some(Token::Minus { line, column }),
+        Token::StarEqual { line, column } => Some(Token::Star { line, column }),
+        Token::SlashEqual { line, column } => Some(Token::Slash { line, column }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{printer, runner::MIN_STACK, testing};
+    use std::{io::Cursor, thread};
+
+    fn parse_status(source: &str) -> (i32, String) {
+        testing::run_with(source, &Default::default(), |interpreter| {
+            interpreter.set_input(Cursor::new(""))
+        })
+    }
+
+    // Test threads get a smaller stack than the CLI gives the interpreter.
+    fn on_cli_stack<T: Send>(run: impl FnOnce() -> T + Send) -> T {
+        thread::scope(|scope| {
+            thread::Builder::new()
+                .stack_size(MIN_STACK)
+                .spawn_scoped(scope, run)
+                .unwrap()
+                .join()
+                .unwrap()
+        })
+    }
+
+    #[test]
+    fn deep_nesting_is_a_single_parse_error() {
+        let n = 200_000;
+        let sources = [
+            format!("print {}1{};", "(".repeat(n), ")".repeat(n)),
+            format!("print {}1;", "- ".repeat(n)),
+            format!("print {}true;", "!".repeat(n)),
+            format!("{}{}", "{".repeat(n), "}".repeat(n)),
+            format!("var a; {}1;", "a = ".repeat(n)),
+        ];
+
+        for source in sources {
+            let (status, output) = on_cli_stack(|| parse_status(&source));
+
+            assert_eq!(status, 65);
+            assert_eq!(output.matches("ParserError").count(), 1);
+            assert!(output.contains("Can not nest more than 128 levels deep."));
+        }
+    }
+
+    #[test]
+    fn nesting_under_the_limit_runs() {
+        for source in [
+            format!("print {}1{};", "(".repeat(120), ")".repeat(120)),
+            format!("print {}1;", "- ".repeat(120)),
+            format!("{}print 1;{}", "{".repeat(120), "}".repeat(120)),
+        ] {
+            assert_eq!(on_cli_stack(|| testing::run(&source)), "1\n");
+        }
+    }
+
+    // Feeds random input through the whole pipeline. Anything it finds wrong
+    // has to come back as a diagnostic, never as a panic or a stack overflow.
+    #[test]
+    fn random_input_never_panics() {
+        let alphabet = b"(){}[];:,.-+*/%!=<>?&|^\"'0123456789_ abcefilnorstuvw\n";
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for round in 0..2000 {
+            let length = (next() % 64) as usize;
+            let bytes: Vec<u8> = (0..length)
+                .map(|_| match round % 2 {
+                    0 => next() as u8,
+                    _ => alphabet[next() as usize % alphabet.len()],
+                })
+                .collect();
+
+            parse_status(&String::from_utf8_lossy(&bytes));
+        }
+    }
+
+    fn ast(source: &str) -> Vec<String> {
+        testing::parse(source)
+            .iter()
+            .map(printer::print_stmt)
+            .collect()
+    }
+
+    #[test]
+    fn concise_functions_return_their_expression() {
+        assert_eq!(
+            ast("fun sq(x) => x * x;"),
+            ["(fun sq (x) (return (* x x)))"]
+        );
+        assert_eq!(testing::run("fun sq(x) => x * x; print sq(3);"), "9\n");
+    }
+
+    #[test]
+    fn concise_lambdas_return_their_expression() {
+        assert_eq!(
+            ast("var inc = fun (x) => x + 1;"),
+            ["(var inc (fun (x) (return (+ x 1))))"]
+        );
+        assert_eq!(
+            testing::run("var inc = fun (x) => x + 1; print inc(1);"),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn block_functions_need_an_explicit_return() {
+        assert_eq!(ast("fun sq(x) { x * x; }"), ["(fun sq (x) (; (* x x)))"]);
+        assert_eq!(testing::run("fun sq(x) { x * x; } print sq(3);"), "nil\n");
+        assert_eq!(
+            testing::run("fun sq(x) { return x * x; } print sq(3);"),
+            "9\n"
+        );
+    }
+
+    #[test]
+    fn concise_functions_need_a_semicolon() {
+        let (status, output) = parse_status("fun sq(x) => x * x\nprint sq(3);");
+
+        assert_eq!(status, 65);
+        assert!(
+            output.contains("Expected ';' after function body."),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn postfix_increment_works_as_a_statement() {
+        let output = testing::run("var i = 1; i++; print i; i--; i--; print i;");
+
+        assert_eq!(output, "2\n0\n");
+    }
+
+    #[test]
+    fn postfix_increment_works_in_a_for_increment() {
+        let output = testing::run("for (var i = 0; i < 3; i++) print i;");
+
+        assert_eq!(output, "0\n1\n2\n");
+    }
+
+    #[test]
+    fn postfix_increment_is_rejected_in_an_expression() {
+        for source in [
+            "var i = 1; print i++;",
+            "var i = 1; var j = i--;",
+            "var i = 1; i = i++;",
+        ] {
+            let (status, output) = testing::run_with(source, &Default::default(), |_| ());
+
+            assert_eq!(status, 65, "{source}");
+            assert!(output.contains("as a statement"), "{output}");
+        }
+    }
+
+    #[test]
+    fn function_source_keeps_the_whole_last_token() {
+        let output = testing::run("fun f(x) => x * 22; print source(f);");
+
+        assert_eq!(output, "=> x * 22\n");
+    }
+
+    #[test]
+    fn function_source_keeps_a_trailing_string() {
+        let output = testing::run("fun f(x) => x + \"abc\"; print source(f);");
+
+        assert_eq!(output, "=> x + \"abc\"\n");
+    }
+
+    #[test]
+    fn function_source_keeps_a_trailing_decimal() {
+        let output = testing::run("fun f(x) => x * 1_000.25; print source(f);");
+
+        assert_eq!(output, "=> x * 1_000.25\n");
+    }
+
+    #[test]
+    fn function_source_keeps_a_trailing_multiline_string() {
+        let output = testing::run("fun f() => \"a\nbc\"; print source(f);");
+
+        assert_eq!(output, "=> \"a\nbc\"\n");
+    }
+
+    #[test]
+    fn function_source_spans_a_multiline_block() {
+        let output = testing::run("fun f(x) {\n  return x;\n}\nprint source(f);");
+
+        assert_eq!(output, "{\n  return x;\n}\n");
+    }
+
+    #[test]
+    fn reports_every_parse_error_and_runs_nothing() {
+        let (status, output) = parse_status("print ;\nvar = 1;\nprint 2;\nprint (;");
+
+        assert_eq!(status, 65);
+        assert_eq!(output.matches("ParserError").count(), 3, "{output}");
+        assert!(
+            output.contains("Line 1 - ParserError: Expected expression."),
+            "{output}"
+        );
+        assert!(
+            output.contains("Line 2 - ParserError: Expected identifier."),
+            "{output}"
+        );
+        assert!(output.contains("Line 4 - ParserError"), "{output}");
+        assert!(!output.contains("2\n"), "{output}");
+    }
+
+    #[test]
+    fn parses_a_do_while_loop() {
+        assert_eq!(ast("do print 1; while (false);"), ["(do (print 1) false)"]);
+
+        let (status, output) = parse_status("do print 1; while (false)");
+
+        assert_eq!(status, 65);
+        assert!(
+            output.contains("Expected ';' after do-while condition."),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn parses_anonymous_functions_as_expressions() {
+        assert_eq!(
+            ast("var f = fun (a, b) { return a; };"),
+            ["(var f (fun (a b) (return a)))"]
+        );
+    }
+
+    #[test]
+    fn control_flow_keywords_are_not_expressions() {
+        for (source, message) in [
+            (
+                "while (true) { print break; }",
+                "Can not use 'break' in an expression.",
+            ),
+            (
+                "fun f() { var a = return 1; }",
+                "Can not use 'return' in an expression.",
+            ),
+            (
+                "while (true) { f(continue); }",
+                "Can not use 'continue' as an argument.",
+            ),
+        ] {
+            let (status, output) = parse_status(source);
+
+            assert_eq!(status, 65, "{source}");
+            assert!(output.contains(message), "{output}");
+        }
+    }
+
+    #[test]
+    fn control_flow_keywords_are_fine_as_statements() {
+        assert_eq!(
+            ast("while (true) { break; }"),
+            ["(while true (block (break)))"]
+        );
+    }
+
+    #[test]
+    fn slash_equal_desugars_to_a_division() {
+        assert_eq!(ast("a /= 2;"), ["(; (= a (/ a 2)))"]);
+    }
+
+    #[test]
+    fn compound_assignments_share_one_desugaring() {
+        assert_eq!(
+            ast("a += 1; a -= 2; a *= 3;"),
+            [
+                "(; (= a (+ a 1)))",
+                "(; (= a (- a 2)))",
+                "(; (= a (* a 3)))"
+            ]
+        );
+    }
+
+    #[test]
+    fn compound_assignment_to_an_index_binds_the_target_once() {
+        assert_eq!(
+            ast("l[i] += 1;"),
+            ["(; (block (var  object l) (var  index i) \
+             (; (set  object  index (+ (index  object  index) 1)))))"]
+        );
+    }
+
+    #[test]
+    fn compound_assignment_needs_a_variable_target() {
+        let (status, output) = parse_status("1 += 2;");
+
+        assert_eq!(status, 65);
+        assert!(output.contains("Invalid assignment target."));
+    }
+
+    #[test]
+    fn parses_simultaneous_assignment() {
+        assert_eq!(ast("a, b = b, a;"), ["(= (a b) (b a))"]);
+    }
+
+    #[test]
+    fn simultaneous_assignment_needs_names_and_an_equals() {
+        assert!(
+            parse_status("a, 1 = 2, 3;")
+                .1
+                .contains("Expected variable name after ','.")
+        );
+        assert!(
+            parse_status("a, b 3;")
+                .1
+                .contains("Expected '=' after assignment targets.")
+        );
+    }
+
+    #[test]
+    fn bitwise_operators_bind_between_comparison_and_term() {
+        assert_eq!(
+            ast("print 1 | 2 ^ 3 & 4 << 5 + 6;"),
+            ["(print (| 1 (^ 2 (& 3 (<< 4 (+ 5 6))))))"]
+        );
+        assert_eq!(ast("print 1 < 2 << 3;"), ["(print (< 1 (<< 2 3)))"]);
+    }
+
+    #[test]
+    fn parses_a_deferred_statement() {
+        assert_eq!(ast("{ defer print 1; }"), ["(block (defer (print 1)))"]);
+    }
+
+    #[test]
+    fn parses_several_declarations_in_one_var() {
+        assert_eq!(
+            ast("var a = 1, b, c = a + 1;"),
+            ["(vars (var a 1) (var b nil) (var c (+ a 1)))"]
+        );
+    }
+
+    #[test]
+    fn every_declaration_after_a_comma_needs_a_name() {
+        assert!(
+            parse_status("var a = 1, ;")
+                .1
+                .contains("ParserError: Expected identifier.")
+        );
+        assert!(
+            parse_status("var a = 1, 2;")
+                .1
+                .contains("ParserError: Expected identifier.")
+        );
+    }
+
+    #[test]
+    fn parses_a_constant_declaration() {
+        assert_eq!(ast("const k = 1;"), ["(const k 1)"]);
+    }
+
+    #[test]
+    fn constants_need_an_initializer() {
+        assert!(
+            parse_status("const k;")
+                .1
+                .contains("ParserError: Expected '=' after constant name.")
+        );
+    }
+
+    #[test]
+    fn parses_map_literals_in_expression_position() {
+        assert_eq!(
+            ast("var m = {\"a\": 1, 2: \"b\",};"),
+            ["(var m (map (\"a\" 1) (2 \"b\")))"]
+        );
+        assert_eq!(ast("{}"), ["(block)"]);
+    }
+
+    #[test]
+    fn parses_index_reads_and_writes() {
+        assert_eq!(
+            ast("m[\"a\"] = m[\"b\"];"),
+            ["(; (set m \"a\" (index m \"b\")))"]
+        );
+    }
+
+    #[test]
+    fn for_in_desugars_to_an_index_loop() {
+        assert_eq!(
+            ast("for (x in l) print x;"),
+            ["(block (var  list l) (var  index 0) \
+                 (while (<  index (call <fn len>  list)) \
+                 (block (var x (index  list  index)) (print x)) (=  index (+  index 1))))"]
+        );
+    }
+
+    #[test]
+    fn nil_coalescing_binds_just_above_assignment() {
+        assert_eq!(
+            ast("a = b ?? c ?? d or e;"),
+            ["(; (= a (?? (?? b c) (or d e))))"]
+        );
+    }
+
+    #[test]
+    fn parses_a_block_in_expression_position() {
+        assert_eq!(
+            ast("var x = { var t = 1; t * 2 };"),
+            ["(var x (block (var t 1) (; (* t 2))))"]
+        );
+    }
+
+    #[test]
+    fn call_arguments_are_expressions() {
+        assert_eq!(
+            ast("f(a, b = 1, g(c), fun () {});"),
+            ["(; (call f a (= b 1) (call g c) (fun ())))"]
+        );
+    }
+
+    #[test]
+    fn call_arguments_can_not_be_statements() {
+        let (status, output) = parse_status("f(var a = 1);");
+
+        assert_eq!(status, 65);
+        assert!(
+            output.contains("ParserError: Expected expression."),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn invalid_assignment_targets_are_parse_errors() {
+        for source in [
+            "var a; a + 1 = 2;",
+            "var a; (a) = 1;",
+            "f() = 1;",
+            "\"s\" = 1;",
+        ] {
+            let (status, output) = parse_status(source);
+
+            assert_eq!(status, 65, "{source}");
+            assert!(
+                output.contains("ParserError: Invalid assignment target."),
+                "{output}"
+            );
+        }
+    }
+
+    #[test]
+    fn parsing_continues_after_an_invalid_assignment_target() {
+        let (_, output) = parse_status("f() = 1; var b = 2; g() = 2;");
+
+        assert_eq!(
+            output.matches("Invalid assignment target.").count(),
+            2,
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn unterminated_function_bodies_point_at_their_opening_brace() {
+        let (status, output) = parse_status("print 0;\nfun f() {\n  print 1;\n\nprint 2;\n");
+
+        assert_eq!(status, 65);
+        assert_eq!(
+            output,
+            "fun f() {\n        ^ -- Here\n\
+             <test> @ Line 2 - ParserError: Unterminated block opened at line 2.\n"
+        );
+    }
+
+    #[test]
+    fn the_innermost_unclosed_block_is_reported() {
+        let (_, output) = parse_status("{\n  {\n    print 1;\n  }\nwhile (true) {\n  print 2;");
+
+        assert!(
+            output.contains("Line 5 - ParserError: Unterminated block opened at line 5."),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn logical_chains_associate_to_the_left() {
+        assert_eq!(
+            ast("print a or b or c; print a and b and c;"),
+            ["(print (or (or a b) c))", "(print (and (and a b) c))"]
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            ast("print a or b and c; print a and b or c;"),
+            ["(print (or a (and b c)))", "(print (or (and a b) c))"]
+        );
+    }
+
+    #[test]
+    fn unary_operators_stack_as_prefixes() {
+        assert_eq!(
+            ast("print !!true; print - -5; print -f(1) * 2; print !a == b;"),
+            [
+                "(print (! (! true)))",
+                "(print (- (- 5)))",
+                "(print (* (- (call f 1)) 2))",
+                "(print (== (! a) b))"
+            ]
+        );
+    }
+}
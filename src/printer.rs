@@ -0,0 +1,197 @@
+use crate::{
+    expressions::{Expr, Literal},
+    statements::Stmt,
+    tokens::Token,
+};
+
+pub fn print_stmt(stmt: &Stmt) -> String {
+    match stmt {
+        Stmt::Print { expr } => format!("(print {})", print_expr(expr)),
+        Stmt::Var { name, expr, .. } => format!("(var {} {})", name, print_expr(expr)),
+        Stmt::Const { name, expr, .. } => format!("(const {} {})", name, print_expr(expr)),
+        Stmt::While {
+            condition,
+            body,
+            increment,
+        } => match increment {
+            Some(increment) => format!(
+                "(while {} {} {})",
+                print_expr(condition),
+                print_stmt(body),
+                print_expr(increment)
+            ),
+            None => format!("(while {} {})", print_expr(condition), print_stmt(body)),
+        },
+        Stmt::DoWhile { body, condition } => {
+            format!("(do {} {})", print_stmt(body), print_expr(condition))
+        }
+        Stmt::Break { .. } => String::from("(break)"),
+        Stmt::Continue { .. } => String::from("(continue)"),
+        Stmt::Defer { stmt, .. } => format!("(defer {})", print_stmt(stmt)),
+        Stmt::Return { expr, .. } => format!("(return {})", print_expr(expr)),
+        Stmt::Conditional {
+            condition,
+            then_branch,
+            else_branch,
+        } => match else_branch {
+            Some(else_branch) => format!(
+                "(if {} {} {})",
+                print_expr(condition),
+                print_stmt(then_branch),
+                print_stmt(else_branch)
+            ),
+            None => format!("(if {} {})", print_expr(condition), print_stmt(then_branch)),
+        },
+        Stmt::Block { statements, .. } => parenthesize("block", statements.iter().map(print_stmt)),
+        Stmt::Expression { expr } => format!("(; {})", print_expr(expr)),
+        Stmt::MultiAssign { targets, values } => format!(
+            "(= {} {})",
+            list(targets.iter().map(print_expr)),
+            list(values.iter().map(print_expr))
+        ),
+        Stmt::MultiVar { declarations } => {
+            parenthesize("vars", declarations.iter().map(print_stmt))
+        }
+        Stmt::Function {
+            name, params, body, ..
+        } => parenthesize(
+            &format!("fun {} ({})", name, params.join(" ")),
+            body.iter().map(print_stmt),
+        ),
+    }
+}
+
+pub fn print_expr(expr: &Expr) -> String {
+    match expr {
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        }
+        | Expr::Logical {
+            left,
+            operator,
+            right,
+        } => format!(
+            "({} {} {})",
+            operator.symbol(),
+            print_expr(left),
+            print_expr(right)
+        ),
+        Expr::Grouping { expression } => format!("(group {})", print_expr(expression)),
+        Expr::Literal { value } => match value {
+            Literal::String(value) => format!("{:?}", value),
+            value => value.to_string(),
+        },
+        Expr::Unary { operator, right } => {
+            format!("({} {})", operator.symbol(), print_expr(right))
+        }
+        Expr::Variable { name, .. } => print_name(name),
+        Expr::Assign { name, value, .. } => {
+            format!("(= {} {})", print_name(name), print_expr(value))
+        }
+        Expr::Call {
+            callee, arguments, ..
+        } => parenthesize(
+            &format!("call {}", print_expr(callee)),
+            arguments.iter().map(print_expr),
+        ),
+        Expr::Map { entries, .. } => parenthesize(
+            "map",
+            entries
+                .iter()
+                .map(|(key, value)| format!("({} {})", print_expr(key), print_expr(value))),
+        ),
+        Expr::Block { statements, .. } => parenthesize("block", statements.iter().map(print_stmt)),
+        Expr::Index { object, index, .. } => {
+            format!("(index {} {})", print_expr(object), print_expr(index))
+        }
+        Expr::Set {
+            object,
+            index,
+            value,
+            ..
+        } => format!(
+            "(set {} {} {})",
+            print_expr(object),
+            print_expr(index),
+            print_expr(value)
+        ),
+        Expr::Lambda { params, body, .. } => parenthesize(
+            &format!("fun ({})", params.join(" ")),
+            body.iter().map(print_stmt),
+        ),
+    }
+}
+
+fn print_name(name: &Token) -> String {
+    match name {
+        Token::Identifier { value, .. } => value.to_owned(),
+        token => token.symbol().to_owned(),
+    }
+}
+
+fn list(parts: impl Iterator<Item = String>) -> String {
+    format!("({})", parts.collect::<Vec<_>>().join(" "))
+}
+
+fn parenthesize(name: &str, parts: impl Iterator<Item = String>) -> String {
+    let mut out = format!("({name}");
+
+    for part in parts {
+        out.push(' ');
+        out.push_str(&part);
+    }
+
+    out.push(')');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(value: f64) -> Expr {
+        Expr::Literal {
+            value: Literal::Number(value),
+        }
+    }
+
+    fn binary(left: Expr, operator: Token, right: Expr) -> Expr {
+        Expr::Binary {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        }
+    }
+
+    #[test]
+    fn prints_a_hand_built_expression() {
+        let product = binary(number(2.0), Token::Star { line: 1, column: 1 }, number(3.0));
+        let sum = binary(number(1.0), Token::Plus { line: 1, column: 1 }, product);
+
+        assert_eq!(print_expr(&sum), "(+ 1 (* 2 3))");
+    }
+
+    #[test]
+    fn prints_unary_groups_and_strings() {
+        let expr = Expr::Unary {
+            operator: Token::Minus { line: 1, column: 1 },
+            right: Box::new(Expr::Grouping {
+                expression: Box::new(Expr::Literal {
+                    value: Literal::String("a b".to_owned()),
+                }),
+            }),
+        };
+
+        assert_eq!(print_expr(&expr), "(- (group \"a b\"))");
+    }
+
+    #[test]
+    fn prints_statements() {
+        let stmt = Stmt::Print { expr: number(1.5) };
+
+        assert_eq!(print_stmt(&stmt), "(print 1.5)");
+        assert_eq!(print_stmt(&Stmt::block(vec![stmt])), "(block (print 1.5))");
+    }
+}
@@ -7,23 +7,39 @@ use crate::{
 };
 use std::collections::HashMap;
 
-struct Resolver<'src> {
-    interpreter: &'src mut Interpreter<'src>,
+#[derive(Clone, Copy)]
+enum FunctionType {
+    None,
+    Function,
+}
+
+pub struct Resolver<'a, 'src> {
+    interpreter: &'a mut Interpreter<'src>,
     error: &'src Error,
     scopes: Vec<HashMap<String, bool>>,
+    function: FunctionType,
 }
 
-impl Resolver<'_> {
-    fn new<'src>(interpreter: &'src mut Interpreter<'src>, error: &'src Error) -> Resolver<'src> {
+impl<'a, 'src> Resolver<'a, 'src> {
+    pub fn new(interpreter: &'a mut Interpreter<'src>, error: &'src Error) -> Resolver<'a, 'src> {
         Resolver {
             interpreter,
             error,
             scopes: Vec::new(),
+            function: FunctionType::None,
         }
     }
 
-    fn declare(&mut self, name: &str) {
+    fn declare(&mut self, name: &str, (line, column): (usize, usize)) {
         if let Some(scope) = self.scopes.last_mut() {
+            if scope.contains_key(name) {
+                self.error.report(
+                    (&line, &column),
+                    ErrorType::ResolverError,
+                    "Already a variable with this name in this scope.",
+                );
+            }
+
             scope.insert(name.to_owned(), false);
         }
     }
@@ -34,16 +50,18 @@ impl Resolver<'_> {
         }
     }
 
-    fn resolve(&mut self, statements: Vec<Stmt>) {
+    pub fn resolve(&mut self, statements: Vec<Stmt>) {
         for statement in statements {
             self.resolve_stmt(statement);
         }
     }
 
-    fn resolve_loc(&mut self, expr: Expr, name: &str) {
+    fn resolve_loc(&mut self, id: usize, name: &str) {
         for (i, scope) in self.scopes.iter().enumerate().rev() {
             if scope.contains_key(name) {
-                // self.interpreter.locals.insert(expr, i);
+                self.interpreter
+                    .locals
+                    .insert(id, self.scopes.len() - 1 - i);
                 return;
             }
         }
@@ -52,22 +70,18 @@ impl Resolver<'_> {
     fn resolve_stmt(&mut self, stmt: Stmt) {
         match stmt {
             Stmt::Print { expr } => self.resolve_expr(expr),
-            Stmt::Function { name, params, body } => {
-                if let Some(name) = name {
-                    self.declare(&name);
-                    self.define(&name);
-                }
-
-                self.scopes.push(HashMap::new());
-
-                for param in params {
-                    self.declare(&param);
-                    self.define(&param);
-                }
-
-                self.resolve(body);
+            Stmt::Function {
+                name,
+                params,
+                body,
+                line,
+                column,
+                ..
+            } => {
+                self.declare(&name, (line, column));
+                self.define(&name);
 
-                self.scopes.pop();
+                self.resolve_function(params, body, (line, column));
             }
             Stmt::Conditional {
                 condition,
@@ -80,67 +94,139 @@ impl Resolver<'_> {
                     self.resolve_stmt(*else_branch);
                 }
             }
-            Stmt::Return { expr } => self.resolve_expr(expr),
-            Stmt::While { condition, body } => {
+            Stmt::Return { expr, line, column } => {
+                if let FunctionType::None = self.function {
+                    self.error.report(
+                        (&line, &column),
+                        ErrorType::ResolverError,
+                        "Can't return from top-level code.",
+                    );
+                }
+
+                self.resolve_expr(expr);
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
                 self.resolve_expr(condition);
                 self.resolve_stmt(*body);
+                if let Some(increment) = increment {
+                    self.resolve_expr(increment);
+                }
+            }
+            Stmt::DoWhile { body, condition } => {
+                self.resolve_stmt(*body);
+                self.resolve_expr(condition);
             }
             Stmt::Expression { expr } => self.resolve_expr(expr),
-            Stmt::Block { statements } => {
-                self.resolve(statements);
+            Stmt::Defer { stmt, .. } => self.resolve_stmt(*stmt),
+            Stmt::MultiAssign { targets, values } => {
+                for value in values {
+                    self.resolve_expr(value);
+                }
+
+                for target in targets {
+                    if let Expr::Variable {
+                        name: Token::Identifier { value, .. },
+                        id,
+                    } = target
+                    {
+                        self.resolve_loc(id, &value);
+                    }
+                }
+            }
+            Stmt::Block {
+                statements,
+                declares,
+            } => {
+                if declares {
+                    self.scopes.push(HashMap::new());
+                    self.resolve(statements);
+                    self.scopes.pop();
+                } else {
+                    self.resolve(statements);
+                }
+            }
+            Stmt::Var {
+                name,
+                expr,
+                line,
+                column,
             }
-            Stmt::Var { name, expr } => {
-                self.declare(&name);
+            | Stmt::Const {
+                name,
+                expr,
+                line,
+                column,
+            } => {
+                self.declare(&name, (line, column));
 
                 self.resolve_expr(expr);
 
                 self.define(&name);
             }
+            Stmt::MultiVar { declarations } => {
+                for declaration in declarations {
+                    self.resolve_stmt(declaration);
+                }
+            }
             _ => (),
         }
     }
 
+    fn resolve_function(&mut self, params: Vec<String>, body: Vec<Stmt>, location: (usize, usize)) {
+        let enclosing = self.function;
+        self.function = FunctionType::Function;
+        self.scopes.push(HashMap::new());
+
+        for param in params {
+            self.declare(&param, location);
+            self.define(&param);
+        }
+
+        self.resolve(body);
+
+        self.scopes.pop();
+        self.function = enclosing;
+    }
+
     fn resolve_expr(&mut self, expr: Expr) {
-        match expr.clone() {
+        match expr {
             Expr::Binary { left, right, .. } => {
                 self.resolve_expr(*left);
                 self.resolve_expr(*right);
             }
-            Expr::Variable { name } => {
-                let token = match name {
-                    Token::Identifier {
-                        value,
-                        line,
-                        column,
-                    } => (value, line, column),
-                    _ => unreachable!(),
+            Expr::Variable { name, id } => {
+                let Token::Identifier {
+                    value,
+                    line,
+                    column,
+                } = name
+                else {
+                    return;
                 };
 
-                if self.scopes.len() > 0 {
-                    if let Some(scope) = self.scopes.last_mut() {
-                        if let Some(value) = scope.get(&token.0) {
-                            if *value {
-                                self.error.report(
-                                    (&token.1, &token.2),
-                                    ErrorType::ResolverError,
-                                    "Can't read local variable in its own initializer.",
-                                );
-                            }
-                        }
-                    }
+                if let Some(scope) = self.scopes.last()
+                    && scope.get(&value) == Some(&false)
+                {
+                    self.error.report_span(
+                        (&line, &column),
+                        value.chars().count(),
+                        ErrorType::ResolverError,
+                        "Can't read local variable in its own initializer.",
+                    );
                 }
 
-                self.resolve_loc(expr, &token.0);
+                self.resolve_loc(id, &value);
             }
-            Expr::Assign { name, value } => {
+            Expr::Assign { name, value, id } => {
                 self.resolve_expr(*value);
 
-                let name = match name {
-                    Token::Identifier { value, .. } => value,
-                    _ => unreachable!(),
-                };
-
-                self.resolve_loc(expr, &name);
+                if let Token::Identifier { value, .. } = name {
+                    self.resolve_loc(id, &value);
+                }
             }
             Expr::Call {
                 callee, arguments, ..
@@ -148,7 +234,7 @@ impl Resolver<'_> {
                 self.resolve_expr(*callee);
 
                 for argument in arguments {
-                    self.resolve_stmt(argument);
+                    self.resolve_expr(argument);
                 }
             }
             Expr::Grouping { expression } => self.resolve_expr(*expression),
@@ -157,7 +243,140 @@ impl Resolver<'_> {
                 self.resolve_expr(*right);
             }
             Expr::Unary { right, .. } => self.resolve_expr(*right),
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            Expr::Block {
+                statements,
+                declares,
+                ..
+            } => self.resolve_stmt(Stmt::Block {
+                statements,
+                declares,
+            }),
+            Expr::Index { object, index, .. } => {
+                self.resolve_expr(*object);
+                self.resolve_expr(*index);
+            }
+            Expr::Set {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.resolve_expr(*object);
+                self.resolve_expr(*index);
+                self.resolve_expr(*value);
+            }
+            Expr::Lambda {
+                params,
+                body,
+                line,
+                column,
+                ..
+            } => self.resolve_function(params, body, (line, column)),
             _ => (),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{runner::Options, testing};
+
+    #[test]
+    fn simultaneous_assignment_targets_resolve_to_their_scope() {
+        assert_eq!(
+            testing::run(
+                "var a = \"global\"; \
+                 { var a = 1; var b = 2; fun f() { a, b = b, a; } f(); print a; } \
+                 print a;"
+            ),
+            "2\nglobal\n"
+        );
+    }
+
+    fn resolve_status(source: &str) -> (i32, String) {
+        testing::run_with(source, &Options::default(), |_| ())
+    }
+
+    #[test]
+    fn top_level_return_is_a_resolver_error() {
+        let (status, output) = resolve_status("print 1; return;");
+
+        assert_eq!(status, 65);
+        assert!(output.contains("ResolverError: Can't return from top-level code."));
+        assert!(!output.starts_with('1'));
+    }
+
+    #[test]
+    fn return_is_fine_inside_functions_and_lambdas() {
+        assert_eq!(
+            testing::run(
+                "fun f() { { return 1; } } var g = fun () { return 2; }; print f(); print g();"
+            ),
+            "1\n2\n"
+        );
+    }
+
+    #[test]
+    fn duplicate_declarations_in_a_scope_are_errors() {
+        let (status, output) = resolve_status("{ var x = 1; var x = 2; }");
+
+        assert_eq!(status, 65);
+        assert!(output.contains("ResolverError: Already a variable with this name in this scope."));
+        assert!(
+            resolve_status("fun f(a) { var a = 1; }")
+                .1
+                .contains("Already a variable")
+        );
+    }
+
+    #[test]
+    fn shadowing_in_an_inner_scope_is_allowed() {
+        assert_eq!(
+            testing::run("{ var y = 1; { var y = 2; print y; } print y; }"),
+            "2\n1\n"
+        );
+    }
+
+    #[test]
+    fn globals_can_be_redeclared() {
+        assert_eq!(testing::run("var x = 1; var x = 2; print x;"), "2\n");
+    }
+
+    #[test]
+    fn closures_keep_the_binding_they_resolved_to() {
+        assert_eq!(
+            testing::run(
+                "var a = \"global\"; \
+                 { fun show() { print a; } show(); var a = \"block\"; show(); }"
+            ),
+            "global\nglobal\n"
+        );
+    }
+
+    #[test]
+    fn closures_assign_to_their_captured_local() {
+        assert_eq!(
+            testing::run(
+                "var n = 10; \
+                 fun counter() { var n = 0; fun inc() { n = n + 1; return n; } return inc; } \
+                 var c = counter(); c(); print c(); print n;"
+            ),
+            "2\n10\n"
+        );
+    }
+
+    #[test]
+    fn one_var_can_not_declare_a_name_twice() {
+        assert!(
+            resolve_status("{ var a = 1, a = 2; }")
+                .1
+                .contains("ResolverError: Already a variable with this name in this scope.")
+        );
+    }
+}
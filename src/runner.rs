@@ -0,0 +1,432 @@
+use crate::{
+    environment::Environment,
+    error::Error,
+    interpreter::{Clock, Control, Interpreter},
+    optimizer, parser, printer, resolver, scanner,
+    statements::Stmt,
+    tokens::Token,
+};
+use std::{
+    io::{Write, stderr, stdin, stdout},
+    time::Duration,
+};
+
+pub const MAX_DEPTH: usize = 1000;
+
+// The CLI never runs the interpreter on less stack than this, which fits the
+// deepest nesting the parser accepts even in a debug build. Hosts calling run
+// on their own threads need about as much.
+pub const MIN_STACK: usize = 8 * 1024 * 1024;
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub clock: Clock,
+    pub trace: bool,
+    pub trace_time: bool,
+    pub time: bool,
+    pub lazy: bool,
+    pub step: bool,
+    pub ast: bool,
+    pub tokens: bool,
+    pub features: Vec<String>,
+    pub max_depth: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            clock: Clock::system(),
+            trace: false,
+            trace_time: false,
+            time: false,
+            lazy: false,
+            step: false,
+            ast: false,
+            tokens: false,
+            features: Vec::new(),
+            max_depth: MAX_DEPTH,
+        }
+    }
+}
+
+impl Options {
+    pub fn interpreter<'src>(&self, error: &'src Error, repl: bool) -> Interpreter<'src> {
+        let mut interpreter = Interpreter::new(error, Environment::new(None), repl, self.max_depth);
+
+        if self.trace || self.trace_time {
+            interpreter.enable_trace();
+        }
+
+        if self.trace_time {
+            interpreter.set_clock(self.clock.clone());
+            interpreter.enable_timing();
+        }
+
+        if self.lazy {
+            interpreter.enable_lazy();
+        }
+
+        for feature in &self.features {
+            interpreter.set_feature(feature, true);
+        }
+
+        interpreter
+    }
+}
+
+struct Phases<'a> {
+    enabled: bool,
+    clock: &'a Clock,
+    started: Duration,
+    log: &'a mut dyn Write,
+}
+
+impl Phases<'_> {
+    fn new<'a>(enabled: bool, clock: &'a Clock, log: &'a mut dyn Write) -> Phases<'a> {
+        Phases {
+            enabled,
+            clock,
+            started: clock.now(),
+            log,
+        }
+    }
+
+    fn lap(&mut self, phase: &str) {
+        let now = self.clock.now();
+
+        if self.enabled {
+            let elapsed = now.saturating_sub(self.started).as_secs_f64() * 1000.0;
+            _ = writeln!(self.log, "{elapsed:>10.3}ms {phase}");
+        }
+
+        self.started = now;
+    }
+}
+
+// Runs a whole script and returns the process exit code for it.
+pub fn run(name: &str, source: String, options: &Options) -> i32 {
+    run_with(name, source, options, |_| ())
+}
+
+// Like run, but lets the host seed globals, features, hooks or the output of
+// the interpreter before the script starts.
+pub fn run_with(
+    name: &str,
+    source: String,
+    options: &Options,
+    setup: impl FnOnce(&mut Interpreter),
+) -> i32 {
+    run_in(
+        &Error::new(name, Some(source.to_owned())),
+        source,
+        options,
+        setup,
+    )
+}
+
+// Runs against an Error the caller owns, so the host decides where
+// diagnostics go.
+pub fn run_in(
+    err: &Error,
+    source: String,
+    options: &Options,
+    setup: impl FnOnce(&mut Interpreter),
+) -> i32 {
+    let mut log = stderr();
+    let mut phases = Phases::new(options.time, &options.clock, &mut log);
+
+    let mut scanner = scanner::Scanner::new(err);
+    let tokens = match scanner.scan_tokens(source.clone()) {
+        Ok(tokens) => tokens,
+        Err(_) => return 65,
+    };
+
+    phases.lap("scan");
+
+    if options.tokens {
+        print_tokens(&tokens);
+        return 0;
+    }
+
+    let statements = match parser::Parser::new(err).parse(tokens, &source) {
+        Ok(stmts) => stmts,
+        Err(_) => return 65,
+    };
+
+    if options.ast {
+        print_ast(&statements);
+        return 0;
+    }
+
+    let statements = optimizer::flatten(statements);
+
+    phases.lap("parse");
+
+    let mut interpreter = options.interpreter(err, false);
+
+    if options.step {
+        interpreter.set_hook(stepper(source.clone()));
+    }
+
+    setup(&mut interpreter);
+
+    phases.lap("setup");
+
+    resolver::Resolver::new(&mut interpreter, err).resolve(statements.clone());
+
+    if err.had_errors() {
+        return 65;
+    }
+
+    phases.lap("resolve");
+
+    interpreter.hoist_functions(&statements);
+
+    let status = match interpreter.interpret(&statements) {
+        Ok(_) => 0,
+        Err(_) => 70,
+    };
+
+    phases.lap("interpret");
+
+    interpreter.report_trace();
+    status
+}
+
+// How far one REPL input got.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    // The input didn't scan.
+    Invalid,
+    // The input scanned, but it was only dumped by --tokens or --ast, or it
+    // stopped at an error.
+    Stopped,
+    // The input ran without errors.
+    Ran,
+}
+
+// Keeps one interpreter alive across REPL inputs, with line numbers that keep
+// counting up from one input to the next.
+pub struct Repl<'src> {
+    err: &'src Error,
+    options: Options,
+    scanner: scanner::Scanner<'src>,
+    parser: parser::Parser<'src>,
+    interpreter: Interpreter<'src>,
+}
+
+impl Repl<'_> {
+    pub fn new<'src>(err: &'src Error, options: &Options) -> Repl<'src> {
+        Repl {
+            err,
+            options: options.clone(),
+            scanner: scanner::Scanner::new(err),
+            parser: parser::Parser::new(err),
+            interpreter: options.interpreter(err, true),
+        }
+    }
+
+    pub fn eval(&mut self, source: &str) -> Outcome {
+        self.err.reset();
+
+        let line = self.err.add_source(source);
+        self.scanner.start_at_line(line);
+        self.parser.start_at_line(line);
+
+        let Ok(tokens) = self.scanner.scan_tokens(source.to_owned()) else {
+            return Outcome::Invalid;
+        };
+
+        if self.options.tokens {
+            print_tokens(&tokens);
+            return Outcome::Stopped;
+        }
+
+        let Ok(statements) = self.parser.parse(tokens, source) else {
+            return Outcome::Stopped;
+        };
+
+        if self.options.ast {
+            print_ast(&statements);
+            return Outcome::Stopped;
+        }
+
+        let statements = optimizer::flatten(statements);
+
+        resolver::Resolver::new(&mut self.interpreter, self.err).resolve(statements.clone());
+
+        if self.err.had_errors() {
+            return Outcome::Stopped;
+        }
+
+        match self.interpreter.interpret(&statements) {
+            Ok(_) => Outcome::Ran,
+            Err(_) => Outcome::Stopped,
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.interpreter.report_trace();
+    }
+}
+
+pub fn list_todos(path: &str, source: String) {
+    let err = Error::new(path, Some(source.to_owned()));
+
+    let mut scanner = scanner::Scanner::new(&err);
+    _ = scanner.scan_tokens(source);
+
+    for (line, comment) in scanner.comments() {
+        let comment = comment.trim();
+
+        if comment.starts_with("TODO") || comment.starts_with("FIXME") {
+            println!("{path}:{line}: {comment}");
+        }
+    }
+}
+
+fn print_tokens(tokens: &[Token]) {
+    for token in tokens {
+        let (line, column) = token.location();
+        println!("{line}:{column} {token}");
+    }
+}
+
+fn print_ast(statements: &[Stmt]) {
+    for stmt in statements {
+        println!("{}", printer::print_stmt(stmt));
+    }
+}
+
+fn stepper(source: String) -> impl FnMut(&Stmt, Option<(usize, usize)>) -> Control {
+    let lines: Vec<String> = source.lines().map(|line| line.to_owned()).collect();
+    let mut running = false;
+
+    move |_, location| {
+        if running {
+            return Control::Continue;
+        }
+
+        if let Some((line, _)) = location {
+            let text = lines.get(line - 1).map_or("", |text| text.trim());
+            print!("[line {line}] {text} ");
+        } else {
+            print!("[line ?] ");
+        }
+
+        _ = stdout().flush();
+
+        let mut command = String::new();
+        match stdin().read_line(&mut command) {
+            Ok(0) | Err(_) => {
+                running = true;
+                Control::Continue
+            }
+            Ok(_) => match command.trim() {
+                "c" => {
+                    running = true;
+                    Control::Continue
+                }
+                "q" => Control::Abort,
+                _ => Control::Step,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Buffer;
+    use std::sync::Mutex;
+
+    #[test]
+    fn phases_report_the_time_since_the_previous_lap() {
+        let readings = Mutex::new(vec![0, 2, 7, 7].into_iter());
+        let clock = Clock::new(move || {
+            Duration::from_micros(readings.lock().unwrap().next().unwrap_or(0) * 1500)
+        });
+        let mut log = Vec::new();
+
+        let mut phases = Phases::new(true, &clock, &mut log);
+        phases.lap("scan");
+        phases.lap("parse");
+        phases.lap("resolve");
+
+        assert_eq!(
+            String::from_utf8(log).unwrap(),
+            "     3.000ms scan\n     7.500ms parse\n     0.000ms resolve\n"
+        );
+    }
+
+    #[test]
+    fn phases_stay_quiet_unless_enabled() {
+        let clock = Clock::system();
+        let mut log = Vec::new();
+
+        let mut phases = Phases::new(false, &clock, &mut log);
+        phases.lap("scan");
+
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn repl_inputs_do_not_hoist_functions() {
+        let err = Error::new("<repl>", None);
+        err.set_output(std::io::sink());
+        let mut repl = Repl::new(&err, &Options::default());
+
+        assert_eq!(repl.eval("f(); fun f() {}"), Outcome::Stopped);
+        assert_eq!(repl.eval("fun f() {}"), Outcome::Ran);
+        assert_eq!(repl.eval("f();"), Outcome::Ran);
+    }
+
+    #[test]
+    fn repl_recovers_its_call_depth_after_a_stack_overflow() {
+        let err = Error::new("<repl>", None);
+        err.set_output(std::io::sink());
+        let options = Options {
+            max_depth: 5,
+            ..Options::default()
+        };
+        let mut repl = Repl::new(&err, &options);
+
+        assert_eq!(repl.eval("fun f() { return f(); } f();"), Outcome::Stopped);
+        assert_eq!(
+            repl.eval("fun g(n) { if (n < 4) return g(n + 1); return n; } g(0);"),
+            Outcome::Ran
+        );
+    }
+
+    #[test]
+    fn repl_errors_report_the_session_line() {
+        let output = Buffer::default();
+        let err = Error::new("<repl>", None);
+        err.set_output(output.clone());
+        let mut repl = Repl::new(&err, &Options::default());
+
+        assert_eq!(repl.eval("var a = 1;\n"), Outcome::Ran);
+        assert_eq!(repl.eval("print a +\n  c;\n"), Outcome::Stopped);
+
+        assert_eq!(
+            output.text(),
+            "c;\n^ -- Here\n<repl> @ Line 3 - RuntimeError: Undefined variable 'c'\n"
+        );
+    }
+
+    #[test]
+    fn repl_scan_and_parse_errors_report_the_session_line() {
+        let output = Buffer::default();
+        let err = Error::new("<repl>", None);
+        err.set_output(output.clone());
+        let mut repl = Repl::new(&err, &Options::default());
+
+        assert_eq!(repl.eval("var a = 1;\n"), Outcome::Ran);
+        assert_eq!(repl.eval("var b = @;\n"), Outcome::Invalid);
+        assert_eq!(repl.eval("\nprint (;\n"), Outcome::Stopped);
+
+        let text = output.text();
+        assert!(text.contains("<repl> @ Line 2 - TokenError"), "{text}");
+        assert!(text.contains("<repl> @ Line 4 - ParserError"), "{text}");
+    }
+}
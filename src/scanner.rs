@@ -1,27 +1,35 @@
 use crate::{error::Error, tokens::Token};
 
 pub struct Scanner<'src> {
-    source: String,
+    source: Vec<char>,
     error: &'src Error,
     had_error: bool,
     start: usize,
     current: usize,
     column: usize,
     line: usize,
+    start_column: usize,
+    start_line: usize,
+    first_line: usize,
     tokens: Vec<Token>,
+    comments: Vec<(usize, String)>,
 }
 
 impl Scanner<'_> {
     pub fn new<'src>(error: &'src Error) -> Scanner<'src> {
         Scanner {
-            source: String::new(),
-            error: error,
+            source: Vec::new(),
+            error,
             had_error: false,
             start: 0,
             current: 0,
             column: 0,
             line: 1,
+            start_column: 0,
+            start_line: 1,
+            first_line: 1,
             tokens: Vec::new(),
+            comments: Vec::new(),
         }
     }
 
@@ -42,11 +50,20 @@ impl Scanner<'_> {
         self.current = 0;
         self.start = 0;
         self.column = 0;
-        self.line = 1;
+        self.line = self.first_line;
         self.tokens = Vec::new();
+        self.comments = Vec::new();
         self.had_error = false;
 
-        self.source = source;
+        self.source = source.chars().collect();
+
+        if self.source.starts_with(&['#', '!']) {
+            self.current = self
+                .source
+                .iter()
+                .position(|&c| c == '\n')
+                .unwrap_or(self.source.len());
+        }
 
         while !self.is_end() {
             self.start = self.current;
@@ -65,6 +82,14 @@ impl Scanner<'_> {
         }
     }
 
+    pub fn start_at_line(&mut self, line: usize) {
+        self.first_line = line;
+    }
+
+    pub fn comments(&self) -> &[(usize, String)] {
+        &self.comments
+    }
+
     fn increment_current(&mut self) {
         self.current += 1;
         self.column += 1;
@@ -76,173 +101,236 @@ impl Scanner<'_> {
     }
 
     fn scan_token(&mut self) {
-        let scan = self.source.get(self.current..self.current + 1).unwrap();
+        let scan = self.source[self.current];
 
         self.current += 1;
         self.column += 1;
 
+        self.start_column = self.column;
+        self.start_line = self.line;
+
         match scan {
-            "(" => self.tokens.push(Token::LeftParen {
-                line: self.line,
-                column: self.column,
+            '(' => self.tokens.push(Token::LeftParen {
+                line: self.start_line,
+                column: self.start_column,
+            }),
+            ')' => self.tokens.push(Token::RightParen {
+                line: self.start_line,
+                column: self.start_column,
             }),
-            ")" => self.tokens.push(Token::RightParen {
-                line: self.line,
-                column: self.column,
+            '{' => self.tokens.push(Token::LeftBrace {
+                line: self.start_line,
+                column: self.start_column,
             }),
-            "{" => self.tokens.push(Token::LeftBrace {
-                line: self.line,
-                column: self.column,
+            '}' => self.tokens.push(Token::RightBrace {
+                line: self.start_line,
+                column: self.start_column,
             }),
-            "}" => self.tokens.push(Token::RightBrace {
-                line: self.line,
-                column: self.column,
+            '[' => self.tokens.push(Token::LeftBracket {
+                line: self.start_line,
+                column: self.start_column,
             }),
-            "," => self.tokens.push(Token::Comma {
-                line: self.line,
-                column: self.column,
+            ']' => self.tokens.push(Token::RightBracket {
+                line: self.start_line,
+                column: self.start_column,
             }),
-            "." => self.tokens.push(Token::Dot {
-                line: self.line,
-                column: self.column,
+            ',' => self.tokens.push(Token::Comma {
+                line: self.start_line,
+                column: self.start_column,
+            }),
+            '.' => self.tokens.push(Token::Dot {
+                line: self.start_line,
+                column: self.start_column,
+            }),
+            ';' => self.tokens.push(Token::Semicolon {
+                line: self.start_line,
+                column: self.start_column,
+            }),
+            '?' => {
+                if self.look_ahead('?') {
+                    self.tokens.push(Token::QuestionQuestion {
+                        line: self.start_line,
+                        column: self.start_column,
+                    });
+                } else {
+                    self.tokens.push(Token::Question {
+                        line: self.start_line,
+                        column: self.start_column,
+                    });
+                }
+            }
+            ':' => self.tokens.push(Token::Colon {
+                line: self.start_line,
+                column: self.start_column,
             }),
-            ";" => self.tokens.push(Token::Semicolon {
-                line: self.line,
-                column: self.column,
+            '&' => self.tokens.push(Token::Ampersand {
+                line: self.start_line,
+                column: self.start_column,
             }),
-            "?" => self.tokens.push(Token::Question {
-                line: self.line,
-                column: self.column,
+            '|' => self.tokens.push(Token::Pipe {
+                line: self.start_line,
+                column: self.start_column,
             }),
-            ":" => self.tokens.push(Token::Colon {
-                line: self.line,
-                column: self.column,
+            '^' => self.tokens.push(Token::Caret {
+                line: self.start_line,
+                column: self.start_column,
             }),
 
-            "-" => {
+            '-' => {
                 if self.look_ahead('=') {
                     self.tokens.push(Token::MinusEqual {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
+                    });
+                } else if self.look_ahead('-') {
+                    self.tokens.push(Token::MinusMinus {
+                        line: self.start_line,
+                        column: self.start_column,
                     });
-                    self.increment_current();
                 } else {
                     self.tokens.push(Token::Minus {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
                     });
                 }
             }
-            "+" => {
+            '+' => {
                 if self.look_ahead('=') {
                     self.tokens.push(Token::PlusEqual {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
+                    });
+                } else if self.look_ahead('+') {
+                    self.tokens.push(Token::PlusPlus {
+                        line: self.start_line,
+                        column: self.start_column,
                     });
-                    self.increment_current();
                 } else {
                     self.tokens.push(Token::Plus {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
                     });
                 }
             }
-            "*" => {
+            '*' => {
                 if self.look_ahead('=') {
                     self.tokens.push(Token::StarEqual {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
                     });
-                    self.increment_current();
                 } else {
                     self.tokens.push(Token::Star {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
                     });
                 }
             }
-            "!" => {
+            '!' => {
                 if self.look_ahead('=') {
                     self.tokens.push(Token::BangEqual {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
                     });
                 } else {
                     self.tokens.push(Token::Bang {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
                     });
                 }
             }
-            "=" => {
+            '=' => {
                 if self.look_ahead('=') {
                     self.tokens.push(Token::EqualEqual {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
+                    });
+                } else if self.look_ahead('>') {
+                    self.tokens.push(Token::FatArrow {
+                        line: self.start_line,
+                        column: self.start_column,
                     });
                 } else {
                     self.tokens.push(Token::Equal {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
                     });
                 }
             }
-            "<" => {
+            '<' => {
                 if self.look_ahead('=') {
                     self.tokens.push(Token::LessEqual {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
+                    });
+                } else if self.look_ahead('<') {
+                    self.tokens.push(Token::LessLess {
+                        line: self.start_line,
+                        column: self.start_column,
                     });
                 } else {
                     self.tokens.push(Token::Less {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
                     });
                 }
             }
-            ">" => {
+            '>' => {
                 if self.look_ahead('=') {
                     self.tokens.push(Token::GreaterEqual {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
+                    });
+                } else if self.look_ahead('>') {
+                    self.tokens.push(Token::GreaterGreater {
+                        line: self.start_line,
+                        column: self.start_column,
                     });
                 } else {
                     self.tokens.push(Token::Greater {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
                     });
                 }
             }
-            "/" => {
+            '/' => {
                 if self.look_ahead('/') {
-                    while self.peek(1) != "\n" && !self.is_end() {
+                    while self.peek(1) != '\n' && !self.is_end() {
                         self.increment_current();
                     }
+
+                    self.comments.push((
+                        self.start_line,
+                        self.source[self.start + 2..self.current].iter().collect(),
+                    ));
+                } else if self.look_ahead('=') {
+                    self.tokens.push(Token::SlashEqual {
+                        line: self.start_line,
+                        column: self.start_column,
+                    });
                 } else {
                     self.tokens.push(Token::Slash {
-                        line: self.line,
-                        column: self.column,
+                        line: self.start_line,
+                        column: self.start_column,
                     });
                 }
             }
 
-            "\"" => self.scan_string("\""),
-            "'" => self.scan_string("'"),
+            '"' => self.scan_string('"'),
+            '\'' => self.scan_string('\''),
 
-            "\r" | "\t" => (),
-            "\n" => self.increment_line(),
-            "   " | " " => self.column -= 1,
+            ' ' | '\r' | '\t' => (),
+            '\n' => self.increment_line(),
 
-            "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" => self.scan_number(),
+            '0'..='9' => self.scan_number(),
 
-            ident @ _ if ident.chars().next().unwrap().is_alphanumeric() => self.scan_identifier(),
+            c if c.is_alphanumeric() => self.scan_identifier(),
 
             c => self.error(&format!("Unexpected character: {}", c)),
         }
     }
 
     fn look_ahead(&mut self, char: char) -> bool {
-        if self.source.chars().nth(self.current).unwrap() == char {
+        if self.source.get(self.current) == Some(&char) {
             self.increment_current();
             true
         } else {
@@ -250,153 +338,519 @@ impl Scanner<'_> {
         }
     }
 
-    fn peek(&mut self, amount: usize) -> &str {
-        if self.current + amount >= self.source.len() {
-            "\0"
-        } else {
-            self.source
-                .get(self.current..self.current + amount)
-                .unwrap()
-        }
+    fn peek(&mut self, amount: usize) -> char {
+        self.source
+            .get(self.current + amount - 1)
+            .copied()
+            .unwrap_or('\0')
     }
 
-    fn scan_string(&mut self, specific: &str) {
+    fn scan_string(&mut self, specific: char) {
         while self.peek(1) != specific && !self.is_end() {
-            if self.peek(1) == "\n" {
+            if self.peek(1) == '\n' {
                 self.increment_line();
             }
 
             self.increment_current();
         }
 
-        self.increment_current();
-
-        if self
-            .source
-            .get(self.current..self.current + 1)
-            .unwrap_or("\0")
-            != specific
-            && self.is_end()
-        {
+        if self.is_end() {
             self.error(&format!("Unterminated string. Expected: {}", specific));
         } else {
+            self.increment_current();
             self.tokens.push(Token::String {
-                value: self
-                    .source
-                    .get(self.start + 1..self.current - 1)
-                    .unwrap()
-                    .to_owned(),
-                line: self.line,
-                column: self.column,
+                value: self.source[self.start + 1..self.current - 1]
+                    .iter()
+                    .collect(),
+                line: self.start_line,
+                column: self.start_column,
             });
         }
     }
 
     fn scan_number(&mut self) {
-        while self.peek(1).chars().next().unwrap().is_digit(10) {
+        while self.peek(1).is_ascii_digit() || self.peek(1) == '_' {
             self.increment_current();
         }
 
-        if self.peek(1) == "." && self.peek(2).chars().nth(1).unwrap().is_digit(10) {
+        if self.peek(1) == '.' && self.peek(2).is_ascii_digit() {
             self.increment_current();
-            while self.peek(1).chars().next().unwrap().is_digit(10) {
+            while self.peek(1).is_ascii_digit() || self.peek(1) == '_' {
                 self.increment_current();
             }
         }
 
-        self.tokens.push(Token::Number {
-            value: self
-                .source
-                .get(self.start..self.current)
-                .unwrap()
-                .parse::<f64>()
-                .unwrap(),
-            line: self.line,
-            column: self.column,
+        let number = &self.source[self.start..self.current];
+        let misplaced = number.iter().enumerate().any(|(i, c)| {
+            *c == '_'
+                && !(i > 0
+                    && number[i - 1].is_ascii_digit()
+                    && number.get(i + 1).is_some_and(|c| c.is_ascii_digit()))
         });
+
+        if misplaced {
+            let number = number.iter().collect::<String>();
+            self.error(&format!("Misplaced digit separator in number: {}", number));
+            return;
+        }
+
+        let number: String = number.iter().filter(|c| **c != '_').collect();
+
+        match number.parse::<f64>() {
+            Ok(value) if value.is_infinite() => {
+                self.error(&format!("Number is too large: {}", number))
+            }
+            Ok(value) => self.tokens.push(Token::Number {
+                value,
+                line: self.start_line,
+                column: self.start_column,
+            }),
+            Err(_) => self.error(&format!("Invalid number: {}", number)),
+        }
     }
 
     fn scan_identifier(&mut self) {
-        while self.peek(1).chars().next().unwrap().is_alphanumeric() || self.peek(1) == "_" {
+        while self.peek(1).is_alphanumeric() || self.peek(1) == '_' {
             self.increment_current();
         }
 
-        let ident = self.source.get(self.start..self.current).unwrap();
+        let ident = &self.source[self.start..self.current];
+
+        // Keywords are short and ASCII, so check them against a stack buffer
+        // and only allocate a String once we know this is an identifier.
+        let mut buffer = [0; 8];
+        let keyword = if ident.len() <= buffer.len() && ident.iter().all(char::is_ascii) {
+            for (byte, c) in buffer.iter_mut().zip(ident) {
+                *byte = *c as u8;
+            }
+
+            std::str::from_utf8(&buffer[..ident.len()]).unwrap_or_default()
+        } else {
+            ""
+        };
 
-        match ident {
+        match keyword {
             "and" => self.tokens.push(Token::And {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "class" => self.tokens.push(Token::Class {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "else" => self.tokens.push(Token::Else {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "false" => self.tokens.push(Token::False {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "fun" => self.tokens.push(Token::Fun {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "for" => self.tokens.push(Token::For {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "if" => self.tokens.push(Token::If {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "nil" => self.tokens.push(Token::Nil {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "or" => self.tokens.push(Token::Or {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "print" => self.tokens.push(Token::Print {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "return" => self.tokens.push(Token::Return {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "break" => self.tokens.push(Token::Break {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
+            }),
+            "continue" => self.tokens.push(Token::Continue {
+                line: self.start_line,
+                column: self.start_column,
+            }),
+            "do" => self.tokens.push(Token::Do {
+                line: self.start_line,
+                column: self.start_column,
+            }),
+            "defer" => self.tokens.push(Token::Defer {
+                line: self.start_line,
+                column: self.start_column,
+            }),
+            "const" => self.tokens.push(Token::Const {
+                line: self.start_line,
+                column: self.start_column,
+            }),
+            "in" => self.tokens.push(Token::In {
+                line: self.start_line,
+                column: self.start_column,
             }),
             "super" => self.tokens.push(Token::Super {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "this" => self.tokens.push(Token::This {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "true" => self.tokens.push(Token::True {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "var" => self.tokens.push(Token::Var {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             "while" => self.tokens.push(Token::While {
-                line: self.line,
-                column: self.column,
+                line: self.start_line,
+                column: self.start_column,
             }),
             _ => self.tokens.push(Token::Identifier {
-                value: ident.to_owned(),
-                line: self.line,
-                column: self.column - (ident.len() - 1),
+                value: ident.iter().collect(),
+                line: self.start_line,
+                column: self.start_column,
             }),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Scanner;
+    use crate::{error::Error, testing};
+
+    fn scan(source: &str) -> Vec<String> {
+        testing::scan(source)
+            .iter()
+            .map(|token| {
+                let (line, column) = token.location();
+                format!("{line}:{column} {token}")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fat_arrow_is_a_single_token() {
+        assert_eq!(
+            scan("=> = == >="),
+            [
+                "1:1 '=>'",
+                "1:4 '='",
+                "1:6 '=='",
+                "1:9 '>='",
+                "1:10 end of file"
+            ]
+        );
+    }
+
+    #[test]
+    fn scans_a_concise_function() {
+        assert_eq!(
+            scan("fun sq(x) => x * x;"),
+            [
+                "1:1 'fun'",
+                "1:5 identifier sq",
+                "1:7 '('",
+                "1:8 identifier x",
+                "1:9 ')'",
+                "1:11 '=>'",
+                "1:14 identifier x",
+                "1:16 '*'",
+                "1:18 identifier x",
+                "1:19 ';'",
+                "1:19 end of file",
+            ]
+        );
+    }
+
+    #[test]
+    fn scans_a_block_function() {
+        assert_eq!(
+            scan("fun sq(x) { return x; }"),
+            [
+                "1:1 'fun'",
+                "1:5 identifier sq",
+                "1:7 '('",
+                "1:8 identifier x",
+                "1:9 ')'",
+                "1:11 '{'",
+                "1:13 'return'",
+                "1:20 identifier x",
+                "1:21 ';'",
+                "1:23 '}'",
+                "1:23 end of file",
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_a_leading_shebang_line() {
+        assert_eq!(
+            scan("#!/usr/bin/env lox\nprint 1;"),
+            ["2:1 'print'", "2:7 number 1", "2:8 ';'", "2:8 end of file"]
+        );
+    }
+
+    #[test]
+    fn a_shebang_alone_scans_to_nothing() {
+        assert_eq!(scan("#!/usr/bin/env lox"), ["1:0 end of file"]);
+    }
+
+    #[test]
+    fn runs_a_script_with_a_shebang() {
+        assert_eq!(testing::run("#!/usr/bin/env lox\nprint 1;"), "1\n");
+    }
+
+    #[test]
+    fn reports_every_unexpected_character() {
+        let (status, output) = testing::run_with(
+            "var a = @; var b = #; print 1;",
+            &Default::default(),
+            |_| (),
+        );
+
+        assert_eq!(status, 65);
+        assert!(
+            output.contains("TokenError: Unexpected character: @"),
+            "{output}"
+        );
+        assert!(
+            output.contains("TokenError: Unexpected character: #"),
+            "{output}"
+        );
+        assert!(!output.contains("1\n"), "{output}");
+    }
+
+    #[test]
+    fn tokens_start_at_their_first_character() {
+        assert_eq!(
+            scan("var name = \"two\nlines\" >= 12.5;\n  foo != bar"),
+            [
+                "1:1 'var'",
+                "1:5 identifier name",
+                "1:10 '='",
+                "1:12 string \"two\\nlines\"",
+                "2:9 '>='",
+                "2:12 number 12.5",
+                "2:16 ';'",
+                "3:3 identifier foo",
+                "3:7 '!='",
+                "3:10 identifier bar",
+                "3:12 end of file",
+            ]
+        );
+    }
+
+    #[test]
+    fn every_whitespace_character_advances_the_column() {
+        assert_eq!(
+            scan("a   b\t\tc\r\n d"),
+            [
+                "1:1 identifier a",
+                "1:5 identifier b",
+                "1:8 identifier c",
+                "2:2 identifier d",
+                "2:2 end of file",
+            ]
+        );
+    }
+
+    #[test]
+    fn non_ascii_text_is_scanned_by_character() {
+        assert_eq!(
+            scan("\"日本\" é // ✓ comment\nx"),
+            [
+                "1:1 string \"日本\"",
+                "1:6 identifier é",
+                "2:1 identifier x",
+                "2:1 end of file",
+            ]
+        );
+    }
+
+    #[test]
+    fn non_ascii_errors_are_reported_instead_of_panicking() {
+        let output = testing::run("print 1; ✓\nprint \"ünterminated");
+
+        assert!(
+            output.contains("TokenError: Unexpected character: ✓"),
+            "{output}"
+        );
+        assert!(
+            output.contains("TokenError: Unterminated string."),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn digit_separators_are_dropped_from_numbers() {
+        assert_eq!(
+            scan("1_000_000 1_000.000_5"),
+            [
+                "1:1 number 1000000",
+                "1:11 number 1000.0005",
+                "1:21 end of file"
+            ]
+        );
+    }
+
+    #[test]
+    fn digit_separators_must_sit_between_digits() {
+        for number in ["1__0", "10_", "1_.5"] {
+            let output = testing::run(&format!("print {number};"));
+
+            assert!(
+                output.contains("TokenError: Misplaced digit separator in number"),
+                "{number}: {output}"
+            );
+        }
+    }
+
+    #[test]
+    fn collects_line_comments_with_their_lines() {
+        let err = Error::new("<test>", None);
+        let mut scanner = Scanner::new(&err);
+        _ = scanner.scan_tokens("// first\nprint 1; // second\n/ 2;".to_owned());
+
+        assert_eq!(
+            scanner.comments(),
+            [(1, " first".to_owned()), (2, " second".to_owned())]
+        );
+    }
+
+    #[test]
+    fn keywords_and_identifiers_scan_apart() {
+        assert_eq!(
+            scan("and class while whilex functions fun"),
+            [
+                "1:1 'and'",
+                "1:5 'class'",
+                "1:11 'while'",
+                "1:17 identifier whilex",
+                "1:24 identifier functions",
+                "1:34 'fun'",
+                "1:36 end of file"
+            ]
+        );
+    }
+
+    #[test]
+    fn non_ascii_identifiers_keep_their_value() {
+        assert_eq!(
+            scan("éfun café"),
+            [
+                "1:1 identifier éfun",
+                "1:6 identifier café",
+                "1:9 end of file"
+            ]
+        );
+    }
+
+    #[test]
+    fn bitwise_operators_scan_apart_from_comparisons() {
+        assert_eq!(
+            scan("& | ^ << >> < <= and"),
+            [
+                "1:1 '&'",
+                "1:3 '|'",
+                "1:5 '^'",
+                "1:7 '<<'",
+                "1:10 '>>'",
+                "1:13 '<'",
+                "1:15 '<='",
+                "1:18 'and'",
+                "1:20 end of file"
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers_too_large_for_a_double_are_errors() {
+        let source = format!("print {};", "9".repeat(400));
+        let (status, output) = testing::run_with(&source, &Default::default(), |_| ());
+
+        assert_eq!(status, 65);
+        assert!(
+            output.contains("TokenError: Number is too large: 999"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn const_is_a_keyword() {
+        assert_eq!(
+            scan("const constant"),
+            ["1:1 'const'", "1:7 identifier constant", "1:14 end of file"]
+        );
+    }
+
+    #[test]
+    fn in_is_a_keyword() {
+        assert_eq!(
+            scan("in inside"),
+            ["1:1 'in'", "1:4 identifier inside", "1:9 end of file"]
+        );
+    }
+
+    #[test]
+    fn double_question_mark_is_one_token() {
+        assert_eq!(scan("?? ?"), ["1:1 '??'", "1:4 '?'", "1:4 end of file"]);
+    }
+
+    #[test]
+    fn numbers_can_end_the_source() {
+        assert_eq!(
+            scan("print 42"),
+            ["1:1 'print'", "1:7 number 42", "1:8 end of file"]
+        );
+        assert_eq!(scan("4.5"), ["1:1 number 4.5", "1:3 end of file"]);
+        assert_eq!(scan("4."), ["1:1 number 4", "1:2 '.'", "1:2 end of file"]);
+    }
+
+    #[test]
+    fn operators_can_end_the_source() {
+        assert_eq!(
+            scan("a =="),
+            ["1:1 identifier a", "1:3 '=='", "1:4 end of file"]
+        );
+        assert_eq!(
+            scan("a ="),
+            ["1:1 identifier a", "1:3 '='", "1:3 end of file"]
+        );
+        assert_eq!(
+            scan("a /"),
+            ["1:1 identifier a", "1:3 '/'", "1:3 end of file"]
+        );
+    }
+
+    #[test]
+    fn identifiers_strings_and_comments_can_end_the_source() {
+        assert_eq!(scan("x"), ["1:1 identifier x", "1:1 end of file"]);
+        assert_eq!(scan("\"x\""), ["1:1 string \"x\"", "1:3 end of file"]);
+        assert_eq!(scan("// only"), ["1:7 end of file"]);
+    }
+
+    #[test]
+    fn an_unclosed_quote_at_the_end_is_an_error() {
+        let (status, output) = testing::run_with("\"", &Default::default(), |_| ());
+
+        assert_eq!(status, 65);
+        assert!(
+            output.contains("TokenError: Unterminated string."),
+            "{output}"
+        );
+    }
+}
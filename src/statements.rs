@@ -8,17 +8,41 @@ pub enum Stmt {
     Var {
         name: String,
         expr: Expr,
+        line: usize,
+        column: usize,
+    },
+    Const {
+        name: String,
+        expr: Expr,
+        line: usize,
+        column: usize,
     },
     While {
         condition: Expr,
         body: Box<Stmt>,
+        increment: Option<Expr>,
+    },
+    DoWhile {
+        body: Box<Stmt>,
+        condition: Expr,
     },
     Break {
         line: usize,
         column: usize,
     },
+    Continue {
+        line: usize,
+        column: usize,
+    },
+    Defer {
+        stmt: Box<Stmt>,
+        line: usize,
+        column: usize,
+    },
     Return {
         expr: Expr,
+        line: usize,
+        column: usize,
     },
     Conditional {
         condition: Expr,
@@ -27,13 +51,127 @@ pub enum Stmt {
     },
     Block {
         statements: Vec<Stmt>,
+        declares: bool,
     },
     Expression {
         expr: Expr,
     },
+    MultiAssign {
+        targets: Vec<Expr>,
+        values: Vec<Expr>,
+    },
+    MultiVar {
+        declarations: Vec<Stmt>,
+    },
     Function {
-        name: Option<String>,
+        name: String,
         params: Vec<String>,
         body: Vec<Stmt>,
+        source: String,
+        line: usize,
+        column: usize,
     },
 }
+
+impl Stmt {
+    pub fn location(&self) -> Option<(usize, usize)> {
+        match self {
+            Stmt::Print { expr }
+            | Stmt::Expression { expr }
+            | Stmt::While {
+                condition: expr, ..
+            }
+            | Stmt::Conditional {
+                condition: expr, ..
+            } => expr.location(),
+            Stmt::DoWhile { body, .. } => body.location(),
+            Stmt::MultiAssign { targets, .. } => targets.first().and_then(Expr::location),
+            Stmt::Var { line, column, .. }
+            | Stmt::Const { line, column, .. }
+            | Stmt::Return { line, column, .. }
+            | Stmt::Break { line, column }
+            | Stmt::Continue { line, column }
+            | Stmt::Defer { line, column, .. } => Some((*line, *column)),
+            Stmt::Block { statements, .. }
+            | Stmt::MultiVar {
+                declarations: statements,
+            } => statements.first().and_then(Stmt::location),
+            Stmt::Function { line, column, .. } => Some((*line, *column)),
+        }
+    }
+
+    // Whether a block needs an environment of its own only depends on what it
+    // holds, so that is worked out once here instead of each time it runs.
+    pub fn block(statements: Vec<Stmt>) -> Stmt {
+        Stmt::Block {
+            declares: statements.iter().any(Stmt::is_declaration),
+            statements,
+        }
+    }
+
+    pub fn is_declaration(&self) -> bool {
+        matches!(
+            self,
+            Stmt::Var { .. } | Stmt::Const { .. } | Stmt::MultiVar { .. } | Stmt::Function { .. }
+        )
+    }
+
+    // Blocks holding a declaration or a defer can't be merged into their
+    // surroundings without changing when those take effect.
+    pub fn is_scoped(&self) -> bool {
+        self.is_declaration() || matches!(self, Stmt::Defer { .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{optimizer, testing};
+
+    fn declares(stmt: &Stmt) -> bool {
+        match stmt {
+            Stmt::Block { declares, .. } => *declares,
+            Stmt::Var {
+                expr: Expr::Block { declares, .. },
+                ..
+            } => *declares,
+            stmt => panic!("expected a block, got {stmt:?}"),
+        }
+    }
+
+    #[test]
+    fn blocks_know_up_front_whether_they_declare() {
+        let statements = testing::parse("{ var a = 1; { print a; } }");
+
+        let Stmt::Block {
+            statements: inner,
+            declares: true,
+        } = &statements[0]
+        else {
+            panic!("expected a declaring block, got {:?}", statements[0]);
+        };
+
+        assert!(!declares(&inner[1]));
+    }
+
+    #[test]
+    fn desugared_loops_declare_their_variables() {
+        let statements = testing::parse("for (var i = 0; i < 1; i = i + 1) print i;");
+
+        assert!(declares(&statements[0]));
+    }
+
+    #[test]
+    fn block_expressions_know_whether_they_declare() {
+        assert!(declares(&testing::parse("var x = { var a = 1; a };")[0]));
+        assert!(!declares(&testing::parse("var x = { 1 + 2 };")[0]));
+    }
+
+    #[test]
+    fn flattening_keeps_the_flag() {
+        let statements = optimizer::flatten(testing::parse("{ { var a; } print 1; { print 2; } }"));
+
+        assert_eq!(statements.len(), 3);
+        assert!(declares(&statements[0]));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{
+    error::Error, interpreter::Interpreter, parser::Parser, runner, scanner::Scanner,
+    statements::Stmt, tokens::Token,
+};
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+#[derive(Clone, Default)]
+pub struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Buffer {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+// Runs a script and returns everything it printed, diagnostics included, in
+// the order it was written.
+pub fn run(source: &str) -> String {
+    run_with(source, &runner::Options::default(), |_| ()).1
+}
+
+// Scans a script that is expected to be valid.
+pub fn scan(source: &str) -> Vec<Token> {
+    let err = Error::new("<test>", Some(source.to_owned()));
+    err.set_output(Buffer::default());
+
+    Scanner::new(&err).scan_tokens(source.to_owned()).unwrap()
+}
+
+// Scans and parses a script that is expected to be valid, without flattening
+// it the way run does.
+pub fn parse(source: &str) -> Vec<Stmt> {
+    let err = Error::new("<test>", Some(source.to_owned()));
+    err.set_output(Buffer::default());
+
+    Parser::new(&err).parse(scan(source), source).unwrap()
+}
+
+pub fn run_with(
+    source: &str,
+    options: &runner::Options,
+    setup: impl FnOnce(&mut Interpreter),
+) -> (i32, String) {
+    let output = Buffer::default();
+    let err = Error::new("<test>", Some(source.to_owned()));
+    err.set_output(output.clone());
+
+    let captured = output.clone();
+    let status = runner::run_in(&err, source.to_owned(), options, |interpreter| {
+        interpreter.set_output(captured);
+        setup(interpreter);
+    });
+
+    (status, output.text())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn captures_output_and_diagnostics_in_order() {
+        let (status, output) = run_with(
+            "print 1;\nprint nil + 1;",
+            &runner::Options::default(),
+            |_| (),
+        );
+
+        assert_eq!(status, 70);
+        assert_eq!(
+            output,
+            "1\nprint nil + 1;\n          ^ -- Here\n<test> @ Line 2 - RuntimeError: Cannot apply '+' to nil.\n"
+        );
+    }
+
+    #[test]
+    fn run_returns_only_the_text() {
+        assert_eq!(run("print \"hi\";"), "hi\n");
+    }
+}
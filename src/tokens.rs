@@ -1,5 +1,7 @@
 // TODO: eventually remove debug
 
+use std::fmt;
+
 #[derive(Debug, Clone)]
 pub enum Token {
     // Single-character tokens
@@ -15,6 +17,14 @@ pub enum Token {
         line: usize,
         column: usize,
     },
+    LeftBracket {
+        line: usize,
+        column: usize,
+    },
+    RightBracket {
+        line: usize,
+        column: usize,
+    },
     RightBrace {
         line: usize,
         column: usize,
@@ -57,6 +67,10 @@ pub enum Token {
     },
 
     // One or two character tokens
+    QuestionQuestion {
+        line: usize,
+        column: usize,
+    },
     Bang {
         line: usize,
         column: usize,
@@ -93,6 +107,14 @@ pub enum Token {
         line: usize,
         column: usize,
     },
+    PlusPlus {
+        line: usize,
+        column: usize,
+    },
+    MinusMinus {
+        line: usize,
+        column: usize,
+    },
     MinusEqual {
         line: usize,
         column: usize,
@@ -101,6 +123,34 @@ pub enum Token {
         line: usize,
         column: usize,
     },
+    SlashEqual {
+        line: usize,
+        column: usize,
+    },
+    FatArrow {
+        line: usize,
+        column: usize,
+    },
+    Ampersand {
+        line: usize,
+        column: usize,
+    },
+    Pipe {
+        line: usize,
+        column: usize,
+    },
+    Caret {
+        line: usize,
+        column: usize,
+    },
+    LessLess {
+        line: usize,
+        column: usize,
+    },
+    GreaterGreater {
+        line: usize,
+        column: usize,
+    },
 
     // Literals
     Identifier {
@@ -168,6 +218,26 @@ pub enum Token {
         line: usize,
         column: usize,
     },
+    Continue {
+        line: usize,
+        column: usize,
+    },
+    Do {
+        line: usize,
+        column: usize,
+    },
+    Defer {
+        line: usize,
+        column: usize,
+    },
+    Const {
+        line: usize,
+        column: usize,
+    },
+    In {
+        line: usize,
+        column: usize,
+    },
     Super {
         line: usize,
         column: usize,
@@ -202,6 +272,8 @@ impl Token {
             Token::LeftParen { line, column } => (line, column),
             Token::RightParen { line, column } => (line, column),
             Token::LeftBrace { line, column } => (line, column),
+            Token::LeftBracket { line, column } => (line, column),
+            Token::RightBracket { line, column } => (line, column),
             Token::RightBrace { line, column } => (line, column),
             Token::Comma { line, column } => (line, column),
             Token::Dot { line, column } => (line, column),
@@ -219,8 +291,17 @@ impl Token {
             Token::Less { line, column } => (line, column),
             Token::LessEqual { line, column } => (line, column),
             Token::PlusEqual { line, column } => (line, column),
+            Token::PlusPlus { line, column } => (line, column),
+            Token::MinusMinus { line, column } => (line, column),
             Token::MinusEqual { line, column } => (line, column),
             Token::StarEqual { line, column } => (line, column),
+            Token::SlashEqual { line, column } => (line, column),
+            Token::FatArrow { line, column } => (line, column),
+            Token::Ampersand { line, column } => (line, column),
+            Token::Pipe { line, column } => (line, column),
+            Token::Caret { line, column } => (line, column),
+            Token::LessLess { line, column } => (line, column),
+            Token::GreaterGreater { line, column } => (line, column),
             Token::Identifier { line, column, .. } => (line, column),
             Token::String { line, column, .. } => (line, column),
             Token::Number { line, column, .. } => (line, column),
@@ -236,14 +317,147 @@ impl Token {
             Token::Print { line, column } => (line, column),
             Token::Return { line, column } => (line, column),
             Token::Break { line, column } => (line, column),
+            Token::Continue { line, column } => (line, column),
+            Token::Do { line, column } => (line, column),
+            Token::Defer { line, column } => (line, column),
+            Token::Const { line, column } => (line, column),
+            Token::In { line, column } => (line, column),
             Token::Super { line, column } => (line, column),
             Token::This { line, column } => (line, column),
             Token::True { line, column } => (line, column),
             Token::Var { line, column } => (line, column),
             Token::While { line, column } => (line, column),
             Token::Question { line, column } => (line, column),
+            Token::QuestionQuestion { line, column } => (line, column),
             Token::Colon { line, column } => (line, column),
             Token::Eof { line, column } => (line, column),
         }
     }
+
+    pub fn width(&self) -> usize {
+        match self {
+            Token::Identifier { value, .. } => value.chars().count(),
+            Token::String { value, .. } => value.chars().count() + 2,
+            Token::Number { value, .. } => value.to_string().len(),
+            Token::Eof { .. } => 1,
+            token => token.symbol().len(),
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Token::LeftParen { .. } => "(",
+            Token::RightParen { .. } => ")",
+            Token::LeftBrace { .. } => "{",
+            Token::LeftBracket { .. } => "[",
+            Token::RightBracket { .. } => "]",
+            Token::RightBrace { .. } => "}",
+            Token::Comma { .. } => ",",
+            Token::Dot { .. } => ".",
+            Token::Minus { .. } => "-",
+            Token::Plus { .. } => "+",
+            Token::Semicolon { .. } => ";",
+            Token::Slash { .. } => "/",
+            Token::Star { .. } => "*",
+            Token::Question { .. } => "?",
+            Token::QuestionQuestion { .. } => "??",
+            Token::Colon { .. } => ":",
+            Token::Bang { .. } => "!",
+            Token::BangEqual { .. } => "!=",
+            Token::Equal { .. } => "=",
+            Token::EqualEqual { .. } => "==",
+            Token::Greater { .. } => ">",
+            Token::GreaterEqual { .. } => ">=",
+            Token::Less { .. } => "<",
+            Token::LessEqual { .. } => "<=",
+            Token::PlusEqual { .. } => "+=",
+            Token::PlusPlus { .. } => "++",
+            Token::MinusMinus { .. } => "--",
+            Token::MinusEqual { .. } => "-=",
+            Token::StarEqual { .. } => "*=",
+            Token::SlashEqual { .. } => "/=",
+            Token::FatArrow { .. } => "=>",
+            Token::Ampersand { .. } => "&",
+            Token::Pipe { .. } => "|",
+            Token::Caret { .. } => "^",
+            Token::LessLess { .. } => "<<",
+            Token::GreaterGreater { .. } => ">>",
+            Token::Identifier { .. } => "identifier",
+            Token::String { .. } => "string",
+            Token::Number { .. } => "number",
+            Token::And { .. } => "and",
+            Token::Class { .. } => "class",
+            Token::Else { .. } => "else",
+            Token::False { .. } => "false",
+            Token::Fun { .. } => "fun",
+            Token::For { .. } => "for",
+            Token::If { .. } => "if",
+            Token::Nil { .. } => "nil",
+            Token::Or { .. } => "or",
+            Token::Print { .. } => "print",
+            Token::Return { .. } => "return",
+            Token::Break { .. } => "break",
+            Token::Continue { .. } => "continue",
+            Token::Do { .. } => "do",
+            Token::Defer { .. } => "defer",
+            Token::Const { .. } => "const",
+            Token::In { .. } => "in",
+            Token::Super { .. } => "super",
+            Token::This { .. } => "this",
+            Token::True { .. } => "true",
+            Token::Var { .. } => "var",
+            Token::While { .. } => "while",
+            Token::Eof { .. } => "end of file",
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Identifier { value, .. } => write!(f, "identifier {}", value),
+            Token::String { value, .. } => write!(f, "string {:?}", value),
+            Token::Number { value, .. } => write!(f, "number {}", value),
+            Token::Eof { .. } => write!(f, "end of file"),
+            token => write!(f, "'{}'", token.symbol()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing;
+
+    #[test]
+    fn width_covers_the_whole_token() {
+        let widths: Vec<usize> = testing::scan("foobar \"hé\" 12.5 + >=")
+            .iter()
+            .map(|token| token.width())
+            .collect();
+
+        assert_eq!(widths, [6, 4, 4, 1, 2, 1]);
+    }
+
+    #[test]
+    fn display_names_the_token_and_its_value() {
+        let shown: Vec<String> = testing::scan("var s = \"hi\"; print 1.5;")
+            .iter()
+            .map(|token| token.to_string())
+            .collect();
+
+        assert_eq!(
+            shown,
+            [
+                "'var'",
+                "identifier s",
+                "'='",
+                "string \"hi\"",
+                "';'",
+                "'print'",
+                "number 1.5",
+                "';'",
+                "end of file"
+            ]
+        );
+    }
 }
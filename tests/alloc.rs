@@ -0,0 +1,67 @@
+use lox_interpreter::{
+    Error, Interpreter, environment::Environment, parser::Parser, resolver::Resolver,
+    scanner::Scanner,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+// Counts the allocations made on the current thread, so tests running in
+// parallel don't see each other's.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static COUNTING: Counting = Counting;
+
+// Runs a script without flattening it first, so its blocks reach the
+// interpreter as written, and returns the allocations made while it ran.
+fn allocations(source: &str) -> usize {
+    let err = Error::new("<alloc>", Some(source.to_owned()));
+    let tokens = Scanner::new(&err).scan_tokens(source.to_owned()).unwrap();
+    let statements = Parser::new(&err).parse(tokens, source).unwrap();
+
+    let mut interpreter = Interpreter::new(&err, Environment::new(None), false, 100);
+    Resolver::new(&mut interpreter, &err).resolve(statements.clone());
+
+    let before = ALLOCATIONS.with(Cell::get);
+    assert!(interpreter.interpret(&statements).is_ok());
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+fn per_iteration(body: &str) -> usize {
+    let run = |n: usize| allocations(&format!("var i = 0; while (i < {n}) {body}"));
+
+    (run(200) - run(100)) / 100
+}
+
+#[test]
+fn blocks_without_declarations_allocate_no_environment() {
+    let bare = per_iteration("i = i + 1;");
+
+    assert_eq!(per_iteration("{ i = i + 1; }"), bare);
+    assert_eq!(per_iteration("{ { i = i + 1; } }"), bare);
+    assert!(per_iteration("{ var j = i; i = j + 1; }") > bare);
+}
+
+#[test]
+fn loop_bodies_run_without_being_cloned() {
+    let large = format!("{{ i = i + 1; {} }}", "i = i + 0; ".repeat(50));
+
+    assert_eq!(per_iteration(&large), per_iteration("{ i = i + 1; }"));
+}
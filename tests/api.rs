@@ -0,0 +1,140 @@
+use lox_interpreter::{Clock, Control, Interpreter, Literal, Options, run_with};
+use std::{
+    cell::RefCell,
+    io::Write,
+    rc::Rc,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+#[derive(Clone, Default)]
+struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Buffer {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+// Moves forward by a millisecond every time it is read.
+fn ticking_clock() -> Clock {
+    let ticks = Arc::new(AtomicU64::new(0));
+    Clock::new(move || Duration::from_millis(ticks.fetch_add(1, Ordering::SeqCst)))
+}
+
+fn run(source: &str, setup: impl FnOnce(&mut Interpreter)) -> (i32, String) {
+    run_options(source, &Options::default(), setup)
+}
+
+fn run_options(
+    source: &str,
+    options: &Options,
+    setup: impl FnOnce(&mut Interpreter),
+) -> (i32, String) {
+    let output = Buffer::default();
+    let captured = output.clone();
+
+    let status = run_with("<test>", source.to_owned(), options, |interpreter| {
+        interpreter.set_output(captured);
+        setup(interpreter);
+    });
+
+    (status, output.text())
+}
+
+#[test]
+fn runs_a_script_through_the_library() {
+    assert_eq!(run("print 1 + 2;", |_| ()), (0, String::from("3\n")));
+}
+
+#[test]
+fn reports_exit_codes_for_errors() {
+    assert_eq!(run("print (;", |_| ()).0, 65);
+    assert_eq!(run("print nil + 1;", |_| ()).0, 70);
+}
+
+#[test]
+fn seeds_host_globals() {
+    let list = Literal::List(Rc::new(RefCell::new(vec![
+        Literal::Number(1.0),
+        Literal::String(String::from("two")),
+    ])));
+
+    let (status, output) = run(
+        "print answer; print hostList; print len(hostList);",
+        |interpreter| {
+            interpreter.set_global("answer", Literal::Number(42.0));
+            interpreter.set_global("hostList", list);
+        },
+    );
+
+    assert_eq!(status, 0);
+    assert_eq!(output, "42\n[1, two]\n2\n");
+}
+
+#[test]
+fn host_globals_replace_builtins_as_ordinary_globals() {
+    let (status, output) = run("print len; len = 2; print len;", |interpreter| {
+        interpreter.set_global("len", Literal::Number(1.0));
+    });
+
+    assert_eq!(status, 0);
+    assert_eq!(output, "1\n2\n");
+}
+
+#[test]
+fn hook_sees_every_statement_and_can_abort() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let lines = seen.clone();
+
+    let (status, output) = run(
+        "var n = 1;\nprint n;\nprint n + 1;\nprint n + 2;\nprint n + 3;",
+        |interpreter| {
+            interpreter.set_hook(move |_, location| {
+                let mut lines = lines.borrow_mut();
+                lines.push(location.map(|(line, _)| line));
+
+                if lines.len() > 3 {
+                    Control::Abort
+                } else {
+                    Control::Continue
+                }
+            });
+        },
+    );
+
+    assert_eq!(status, 70);
+    assert_eq!(output, "1\n2\n");
+    assert_eq!(*seen.borrow(), [Some(1), Some(2), Some(3), Some(4)]);
+}
+
+#[test]
+fn trace_time_reports_time_per_line_without_nested_calls() {
+    let options = Options {
+        clock: ticking_clock(),
+        trace_time: true,
+        ..Options::default()
+    };
+
+    let (status, output) = run_options("fun f() {\n  var c = 3;\n}\nf();", &options, |_| ());
+
+    assert_eq!(status, 0);
+    assert_eq!(
+        output,
+        "-- Calls --\n       1 f\n-- Lines --\n     1.000ms line 1\n     1.000ms line 2\n     2.000ms line 4\n"
+    );
+}
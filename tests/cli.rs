@@ -0,0 +1,169 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn lox(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lox_interpreter"))
+        .args(args)
+        .env("NO_COLOR", "1")
+        .output()
+        .expect("could not run the interpreter")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn eval_runs_a_snippet() {
+    let output = lox(&["--eval", "print 1 + 2;"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "3\n");
+}
+
+#[test]
+fn eval_reports_errors_against_eval() {
+    let output = lox(&["--eval", "print nil + 1;"]);
+
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stdout(&output).contains("<eval> @ Line 1 - RuntimeError"));
+}
+
+#[test]
+fn max_depth_past_the_stack_limit_is_rejected() {
+    let output = lox(&["--max-depth", "100000", "--eval", "print 1;"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--max-depth can be at most 4096"));
+}
+
+#[test]
+fn max_depth_at_the_stack_limit_runs() {
+    let source = "fun f(n) { if (n < 4000) return f(n + 1); return n; } print f(0);";
+    let output = lox(&["--max-depth", "4096", "--eval", source]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "4000\n");
+}
+
+#[test]
+fn time_reports_each_phase_in_order() {
+    let output = lox(&["--time", "--eval", "print 1;"]);
+    let log = String::from_utf8_lossy(&output.stderr).into_owned();
+    let phases: Vec<&str> = log
+        .lines()
+        .map(|line| line.split_whitespace().nth(1).unwrap_or(""))
+        .collect();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "1\n");
+    assert_eq!(phases, ["scan", "parse", "setup", "resolve", "interpret"]);
+    assert!(log.lines().all(|line| line.trim_start().contains("ms ")));
+}
+
+#[test]
+fn todos_lists_todo_and_fixme_comments() {
+    let path = std::env::temp_dir().join(format!("lox-todos-{}.lox", std::process::id()));
+    std::fs::write(
+        &path,
+        "// TODO: one\nprint 1; // FIXME two\n// not this\n// todo lower\n",
+    )
+    .unwrap();
+
+    let output = lox(&["--todos", path.to_str().unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        format!("{0}:1: TODO: one\n{0}:2: FIXME two\n", path.display())
+    );
+}
+
+#[test]
+fn feature_flags_are_enabled_from_the_command_line() {
+    let output = lox(&[
+        "--feature",
+        "a",
+        "--eval",
+        "print feature(\"a\"); print feature(\"b\");",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "true\nfalse\n");
+}
+
+#[test]
+fn scripts_exit_with_the_conventional_status() {
+    let path = std::env::temp_dir().join(format!("lox-status-{}.lox", std::process::id()));
+
+    for (source, status) in [
+        ("print 1;", 0),
+        ("print \"open;", 65),
+        ("print (;", 65),
+        ("return;", 65),
+        ("print nil + 1;", 70),
+    ] {
+        std::fs::write(&path, source).unwrap();
+
+        assert_eq!(
+            lox(&[path.to_str().unwrap()]).status.code(),
+            Some(status),
+            "{source}"
+        );
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn piped_stdin_runs_as_a_script() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lox_interpreter"))
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("could not run the interpreter");
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"var a = 1;\nprint a + 1;\nprint nil + 1;\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stdout(&output).starts_with("2\n"));
+    assert!(stdout(&output).contains("<stdin> @ Line 3 - RuntimeError"));
+}
+
+#[test]
+fn ast_prints_statements_instead_of_running_them() {
+    let output = lox(&["--ast", "--eval", "print 1 + 2 * 3; var a = -a;"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "(print (+ 1 (* 2 3)))\n(var a (- a))\n");
+}
+
+#[test]
+fn tokens_dumps_the_scanner_output() {
+    let output = lox(&["--tokens", "--eval", "var s = \"hi\";\nprint s;"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "1:1 'var'\n1:5 identifier s\n1:7 '='\n1:9 string \"hi\"\n1:13 ';'\n\
+         2:1 'print'\n2:7 identifier s\n2:8 ';'\n2:8 end of file\n"
+    );
+}
+
+#[test]
+fn unbounded_recursion_reports_a_stack_overflow() {
+    let output = lox(&["--eval", "fun f() { return f(); } f();"]);
+
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stdout(&output).contains("RuntimeError: Stack overflow."));
+}